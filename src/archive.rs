@@ -0,0 +1,368 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+#[cfg(unix)]
+use std::os::unix::fs::symlink;
+#[cfg(windows)]
+use std::os::windows::fs::symlink_file as symlink;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use flate2::write::GzEncoder;
+use xz2::write::XzEncoder;
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+use crate::tar::TarWriter;
+use crate::{content, get_file_as_byte_vec, Args, Compression, Entry};
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Zip,
+    Tar,
+    /// Tar compressed with gzip as a whole
+    #[value(name = "tar.gz")]
+    TarGz,
+    /// Tar compressed with zstd as a whole
+    #[value(name = "tar.zst")]
+    TarZst,
+    /// Tar compressed with xz as a whole
+    #[value(name = "tar.xz")]
+    TarXz,
+    /// Numbered folders holding the files themselves (see --transfer)
+    Dir,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transfer {
+    Copy,
+    Hardlink,
+    /// Copy-on-write clone, on Linux filesystems that support it such as Btrfs and XFS
+    Reflink,
+    Move,
+}
+
+impl Transfer {
+    fn name(self) -> &'static str {
+        match self {
+            Transfer::Copy => "copy",
+            Transfer::Hardlink => "hardlink",
+            Transfer::Reflink => "reflink",
+            Transfer::Move => "move",
+        }
+    }
+}
+
+impl Format {
+    /// Also the extension of the archives.
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Zip => "zip",
+            Format::Tar => "tar",
+            Format::TarGz => "tar.gz",
+            Format::TarZst => "tar.zst",
+            Format::TarXz => "tar.xz",
+            Format::Dir => "dir",
+        }
+    }
+
+    /// The folder below the destination that the archives are written to. Folders of
+    /// `--format dir` go into the destination itself.
+    pub fn folder(self) -> &'static str {
+        match self {
+            Format::Zip => "zip",
+            Format::Tar | Format::TarGz | Format::TarZst | Format::TarXz => "tar",
+            Format::Dir => "",
+        }
+    }
+
+    /// What archives.csv lists as the compression, or for folders how the files got there.
+    pub fn compression_name(self, args: &Args) -> &'static str {
+        match self {
+            Format::Zip => args.compression.name(),
+            Format::Tar => "none",
+            Format::TarGz => "gzip",
+            Format::TarZst => "zstd",
+            Format::TarXz => "xz",
+            Format::Dir => args.transfer.name(),
+        }
+    }
+
+    /// Tar archives are compressed as a whole, so `--compression` only applies to zip and
+    /// `--level` is the level of the stream compression.
+    pub fn check_args(self, args: &Args) -> Result<(), String> {
+        if self != Format::Dir && args.transfer != Transfer::Copy {
+            return Err("--transfer applies to --format dir only".to_string());
+        }
+        if self == Format::Zip {
+            return match args.level {
+                Some(level) => args.compression.check_level(level),
+                None => Ok(()),
+            };
+        }
+        if args.compression != Compression::Store {
+            return Err(format!(
+                "--compression applies to zip entries, not to {}",
+                self.name()
+            ));
+        }
+        let levels = match self {
+            Format::TarGz | Format::TarXz => 0..=9,
+            Format::TarZst => 1..=22,
+            _ if args.level.is_some() => {
+                return Err(format!("{} takes no compression level", self.name()))
+            }
+            _ => return Ok(()),
+        };
+        match args.level {
+            Some(level) if !levels.contains(&level) => Err(format!(
+                "{} compression level must be within {}-{}",
+                self.name(),
+                levels.start(),
+                levels.end()
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Writes the entries of one archive.
+pub trait ArchiveWriter {
+    fn add_file(&mut self, name: &str, entry: &Entry) -> io::Result<()>;
+    fn add_symlink(&mut self, name: &str, entry: &Entry, target: &Path) -> io::Result<()>;
+    fn finish(self: Box<Self>) -> io::Result<()>;
+}
+
+/// Creates the archive, or with `--format dir` the folder, at `path`.
+pub fn create<'a>(path: &Path, args: &'a Args) -> io::Result<Box<dyn ArchiveWriter + 'a>> {
+    if args.format == Format::Dir {
+        fs::create_dir(path)?;
+        return Ok(Box::new(Dir {
+            dir: path.to_path_buf(),
+            transfer: args.transfer,
+        }));
+    }
+    let file = File::create(path)?;
+    Ok(match args.format {
+        Format::Zip => Box::new(Zip {
+            zip: ZipWriter::new(file),
+            options: FileOptions::default()
+                .compression_method(args.compression.method())
+                .compression_level(args.level)
+                .unix_permissions(0o755),
+            args,
+        }),
+        Format::Tar => Box::new(Tar(TarWriter::new(BufWriter::new(file)))),
+        Format::TarGz => {
+            let level = args
+                .level
+                .map_or_else(flate2::Compression::default, |level| {
+                    flate2::Compression::new(level as u32)
+                });
+            Box::new(Tar(TarWriter::new(GzEncoder::new(
+                BufWriter::new(file),
+                level,
+            ))))
+        }
+        Format::TarZst => {
+            // Level 0 is zstd's default level.
+            let encoder = zstd::Encoder::new(BufWriter::new(file), args.level.unwrap_or(0))?;
+            Box::new(Tar(TarWriter::new(encoder)))
+        }
+        Format::TarXz => {
+            // Level 6 is xz's default level.
+            let level = args.level.unwrap_or(6) as u32;
+            Box::new(Tar(TarWriter::new(XzEncoder::new(
+                BufWriter::new(file),
+                level,
+            ))))
+        }
+        Format::Dir => unreachable!(),
+    })
+}
+
+struct Zip<'a> {
+    zip: ZipWriter<File>,
+    options: FileOptions,
+    args: &'a Args,
+}
+
+impl ArchiveWriter for Zip<'_> {
+    fn add_file(&mut self, name: &str, entry: &Entry) -> io::Result<()> {
+        let data = get_file_as_byte_vec(entry.path.clone())?;
+        let options = if self.args.compression == Compression::Adaptive
+            && content::is_store_only(&entry.path, &data, &self.args.store_types)
+        {
+            self.options
+                .compression_method(zip::CompressionMethod::Stored)
+                .compression_level(None)
+        } else {
+            self.options
+        };
+        self.zip.start_file(name, options)?;
+        self.zip.write_all(&data)
+    }
+
+    fn add_symlink(&mut self, name: &str, _entry: &Entry, target: &Path) -> io::Result<()> {
+        Ok(self
+            .zip
+            .add_symlink(name, target.to_string_lossy(), self.options)?)
+    }
+
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        self.zip.finish()?;
+        Ok(())
+    }
+}
+
+struct Tar<W: Stream>(TarWriter<W>);
+
+impl<W: Stream> ArchiveWriter for Tar<W> {
+    fn add_file(&mut self, name: &str, entry: &Entry) -> io::Result<()> {
+        let data = get_file_as_byte_vec(entry.path.clone())?;
+        self.0.add_file(name, 0o755, entry.modified, &data)
+    }
+
+    fn add_symlink(&mut self, name: &str, entry: &Entry, target: &Path) -> io::Result<()> {
+        self.0
+            .add_symlink(name, entry.modified, &target.to_string_lossy())
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        self.0.finish()?.close()
+    }
+}
+
+/// The output a tar is written to, which may need to end its compression before it is flushed.
+trait Stream: Write {
+    fn close(self) -> io::Result<()>;
+}
+
+impl Stream for BufWriter<File> {
+    fn close(mut self) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+impl Stream for GzEncoder<BufWriter<File>> {
+    fn close(self) -> io::Result<()> {
+        self.finish()?.flush()
+    }
+}
+
+impl Stream for XzEncoder<BufWriter<File>> {
+    fn close(self) -> io::Result<()> {
+        self.finish()?.flush()
+    }
+}
+
+impl Stream for zstd::Encoder<'static, BufWriter<File>> {
+    fn close(self) -> io::Result<()> {
+        self.finish()?.flush()
+    }
+}
+
+/// The folder of a `--format dir` chunk: `part_000` and so on, or `part_` and the label for
+/// chunks named after a partition.
+pub fn dir_name(label: &str) -> String {
+    match label.parse::<u64>() {
+        Ok(number) => format!("part_{:03}", number),
+        Err(_) => format!("part_{}", label),
+    }
+}
+
+struct Dir {
+    dir: PathBuf,
+    transfer: Transfer,
+}
+
+impl ArchiveWriter for Dir {
+    fn add_file(&mut self, name: &str, entry: &Entry) -> io::Result<()> {
+        let target = self.target(name)?;
+        match self.transfer {
+            Transfer::Copy => fs::copy(&entry.path, &target).map(|_| ()),
+            // Linking a followed symlink would link the symlink itself.
+            Transfer::Hardlink => fs::hard_link(fs::canonicalize(&entry.path)?, &target),
+            Transfer::Reflink => reflink(&entry.path, &target),
+            // A followed symlink is replaced by a copy of what it points to.
+            Transfer::Move if fs::symlink_metadata(&entry.path)?.is_symlink() => {
+                fs::copy(&entry.path, &target)?;
+                fs::remove_file(&entry.path)
+            }
+            Transfer::Move => move_file(&entry.path, &target, |src, dst| {
+                fs::copy(src, dst).map(|_| ())
+            }),
+        }
+    }
+
+    fn add_symlink(&mut self, name: &str, entry: &Entry, target: &Path) -> io::Result<()> {
+        let link = self.target(name)?;
+        if self.transfer == Transfer::Move {
+            return move_file(&entry.path, &link, |_, link| symlink(target, link));
+        }
+        symlink(target, &link)
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Dir {
+    /// Where an entry goes, refusing to replace one that is already there. Folders of entries
+    /// stored with `--keep-paths` are created as needed.
+    fn target(&self, name: &str) -> io::Result<PathBuf> {
+        let target = self.dir.join(name);
+        if fs::symlink_metadata(&target).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} is already in {}", name, self.dir.display()),
+            ));
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        Ok(target)
+    }
+}
+
+/// Renames `src`, or when it is on another filesystem recreates it with `copy` and removes it.
+fn move_file(
+    src: &Path,
+    dst: &Path,
+    copy: impl FnOnce(&Path, &Path) -> io::Result<()>,
+) -> io::Result<()> {
+    match fs::rename(src, dst) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            copy(src, dst)?;
+            fs::remove_file(src)
+        }
+        result => result,
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn reflink(src: &Path, dst: &Path) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let source = File::open(src)?;
+    let target = File::options().write(true).create_new(true).open(dst)?;
+    // SAFETY: both descriptors stay open for the duration of the call.
+    if unsafe { libc::ioctl(target.as_raw_fd(), libc::FICLONE as _, source.as_raw_fd()) } != 0 {
+        let e = io::Error::last_os_error();
+        drop(target);
+        fs::remove_file(dst)?;
+        return Err(io::Error::new(
+            e.kind(),
+            format!("cannot reflink {}: {}", src.display(), e),
+        ));
+    }
+    target.set_permissions(source.metadata()?.permissions())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reflink(src: &Path, _dst: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("cannot reflink {}: only supported on Linux", src.display()),
+    ))
+}
@@ -0,0 +1,152 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// How many leading bytes `detect_mime` looks at.
+const SNIFF_LEN: usize = 512;
+
+/// Signatures checked by `detect_mime`: offset, magic bytes, MIME type.
+const SIGNATURES: &[(usize, &[u8], &str)] = &[
+    (0, b"\xFF\xD8\xFF", "image/jpeg"),
+    (0, b"\x89PNG\r\n\x1A\n", "image/png"),
+    (0, b"GIF8", "image/gif"),
+    (0, b"BM", "image/bmp"),
+    (0, b"II*\x00", "image/tiff"),
+    (0, b"MM\x00*", "image/tiff"),
+    (8, b"WEBP", "image/webp"),
+    (4, b"ftypheic", "image/heic"),
+    (4, b"ftypavif", "image/avif"),
+    (4, b"ftypqt", "video/quicktime"),
+    (4, b"ftyp", "video/mp4"),
+    (8, b"AVI ", "video/x-msvideo"),
+    (0, b"\x1A\x45\xDF\xA3", "video/webm"),
+    (8, b"WAVE", "audio/wav"),
+    (0, b"ID3", "audio/mpeg"),
+    (0, b"\xFF\xFB", "audio/mpeg"),
+    (0, b"OggS", "audio/ogg"),
+    (0, b"fLaC", "audio/flac"),
+    (0, b"%PDF", "application/pdf"),
+    (
+        0,
+        b"\xD0\xCF\x11\xE0\xA1\xB1\x1A\xE1",
+        "application/x-ole-storage",
+    ),
+    (0, b"PK\x03\x04", "application/zip"),
+    (0, b"PK\x05\x06", "application/zip"),
+    (0, b"\x1F\x8B", "application/gzip"),
+    (0, b"BZh", "application/x-bzip2"),
+    (0, b"\xFD7zXZ\x00", "application/x-xz"),
+    (0, b"\x28\xB5\x2F\xFD", "application/zstd"),
+    (0, b"7z\xBC\xAF\x27\x1C", "application/x-7z-compressed"),
+    (0, b"Rar!\x1A\x07", "application/vnd.rar"),
+    (0, b"\x7FELF", "application/x-executable"),
+];
+
+/// Default for `--store-types`: extensions and MIME types of content that is compressed already.
+pub const STORE_ONLY_TYPES: &str = "jpg,jpeg,png,gif,webp,heic,avif,mp4,m4v,mov,mkv,webm,avi,\
+mp3,m4a,aac,ogg,opus,flac,zip,gz,tgz,bz2,xz,zst,7z,rar,jar,docx,xlsx,pptx,odt,ods,odp,epub,\
+image/jpeg,image/png,image/gif,image/webp,image/heic,image/avif,video/mp4,video/quicktime,\
+video/webm,audio/mpeg,audio/ogg,audio/flac,application/zip,application/gzip,\
+application/x-bzip2,application/x-xz,application/zstd,application/x-7z-compressed,\
+application/vnd.rar";
+
+/// Extensions by kind, as used by `kind_of_extension`.
+const KINDS: &[(&str, &[&str])] = &[
+    (
+        "images",
+        &[
+            "jpg", "jpeg", "png", "gif", "bmp", "tif", "tiff", "webp", "heic", "avif", "svg",
+            "raw", "cr2", "cr3", "nef", "arw", "dng", "psd",
+        ],
+    ),
+    (
+        "video",
+        &[
+            "mp4", "m4v", "mov", "avi", "mkv", "webm", "wmv", "mpg", "mpeg",
+        ],
+    ),
+    (
+        "audio",
+        &["mp3", "m4a", "aac", "wav", "flac", "ogg", "opus", "wma"],
+    ),
+    (
+        "documents",
+        &[
+            "pdf", "doc", "docx", "xls", "xlsx", "ppt", "pptx", "odt", "ods", "odp", "rtf", "epub",
+        ],
+    ),
+    (
+        "archives",
+        &[
+            "zip", "gz", "tgz", "bz2", "xz", "zst", "7z", "rar", "tar", "jar",
+        ],
+    ),
+    (
+        "text",
+        &[
+            "txt", "md", "csv", "tsv", "json", "xml", "yaml", "yml", "toml", "html", "htm", "log",
+            "xmp",
+        ],
+    ),
+];
+
+/// The label `--type-buckets ext` gives files without an extension.
+pub const NO_EXTENSION: &str = "noext";
+
+/// The lowercase extension of `path`, or `default` when it has none.
+pub fn extension(path: &Path, default: &str) -> String {
+    path.extension().map_or_else(
+        || default.to_string(),
+        |ext| ext.to_string_lossy().to_lowercase(),
+    )
+}
+
+/// A coarse kind such as `images` or `documents` for the extension of `path`, `other` if unknown.
+pub fn kind_of_extension(path: &Path) -> &'static str {
+    let ext = extension(path, "");
+    KINDS
+        .iter()
+        .find(|(_, extensions)| extensions.contains(&ext.as_str()))
+        .map_or("other", |(kind, _)| kind)
+}
+
+/// Reads the start of the file and returns the MIME type its magic bytes indicate. Files
+/// without a known signature are `text/plain` if they look like UTF-8 text, otherwise
+/// `application/octet-stream`.
+pub fn detect_mime(path: &Path) -> io::Result<&'static str> {
+    let mut head = Vec::with_capacity(SNIFF_LEN);
+    File::open(path)?
+        .take(SNIFF_LEN as u64)
+        .read_to_end(&mut head)?;
+    Ok(mime_of(&head))
+}
+
+/// Whether the extension of `path`, or the MIME type the magic bytes at the start of `data`
+/// indicate, is one of `store_types`.
+pub fn is_store_only(path: &Path, data: &[u8], store_types: &[String]) -> bool {
+    let ext = extension(path, NO_EXTENSION);
+    let mime = mime_of(&data[..data.len().min(SNIFF_LEN)]);
+    store_types
+        .iter()
+        .any(|store_type| store_type.eq_ignore_ascii_case(&ext) || store_type == mime)
+}
+
+fn mime_of(head: &[u8]) -> &'static str {
+    let signature = SIGNATURES.iter().find(|(offset, magic, _)| {
+        head.get(*offset..)
+            .is_some_and(|rest| rest.starts_with(magic))
+    });
+    if let Some((_, _, mime)) = signature {
+        return mime;
+    }
+    let text = match std::str::from_utf8(head) {
+        Ok(_) => true,
+        // The sniffed bytes may end in the middle of a character.
+        Err(e) => e.error_len().is_none(),
+    };
+    if text && !head.contains(&0) {
+        "text/plain"
+    } else {
+        "application/octet-stream"
+    }
+}
@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::path::PathBuf;
+
+use sha2::{Digest, Sha256};
+
+use crate::Entry;
+
+/// A file left out because an earlier file has the same content.
+pub struct Duplicate {
+    pub file: Entry,
+    /// The earlier file, which is archived in its place
+    pub original: PathBuf,
+}
+
+/// Keeps the first of every set of files with identical content and returns the rest as
+/// duplicates. Only files that share their size with another file are hashed. Symlinks stored
+/// as links are never duplicates.
+pub fn dedupe(files: Vec<Entry>) -> io::Result<(Vec<Entry>, Vec<Duplicate>)> {
+    let mut sizes: HashMap<u64, usize> = HashMap::new();
+    for file in files.iter().filter(|file| file.link.is_none()) {
+        *sizes.entry(file.size).or_default() += 1;
+    }
+    let mut originals: HashMap<(u64, [u8; 32]), PathBuf> = HashMap::new();
+    let mut kept = Vec::new();
+    let mut duplicates = Vec::new();
+    for file in files {
+        if file.link.is_some() || sizes[&file.size] < 2 {
+            kept.push(file);
+            continue;
+        }
+        match originals.entry((file.size, hash(&file)?)) {
+            std::collections::hash_map::Entry::Occupied(original) => duplicates.push(Duplicate {
+                original: original.get().clone(),
+                file,
+            }),
+            std::collections::hash_map::Entry::Vacant(slot) => {
+                slot.insert(file.path.clone());
+                kept.push(file);
+            }
+        }
+    }
+    Ok((kept, duplicates))
+}
+
+fn hash(file: &Entry) -> io::Result<[u8; 32]> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(&file.path)?, &mut hasher)?;
+    Ok(hasher.finalize().into())
+}
@@ -0,0 +1,774 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::mem;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use regex::Regex;
+use time::OffsetDateTime;
+
+use crate::{content, Args, Entry, SplitBy};
+
+/// Files that must end up in the same chunk whenever the chunk limit allows it.
+type Unit = Vec<Entry>;
+
+pub struct Chunk {
+    /// Names the archive, e.g. `3` for `dst_3.zip`
+    pub label: String,
+    pub files: Vec<Entry>,
+    /// The limit that made this chunk end, if it ended because of one
+    pub closed_by: Option<Constraint>,
+}
+
+/// Chunk limits used when neither balancing nor a fixed number of parts is asked for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Constraint {
+    /// `--file-count-per-file`
+    Count,
+    /// `--max-bytes`
+    Bytes,
+    /// `--max-path-bytes`
+    PathBytes,
+}
+
+impl Constraint {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Constraint::Count => "count",
+            Constraint::Bytes => "bytes",
+            Constraint::PathBytes => "path-bytes",
+        }
+    }
+
+    fn weight(self, file: &Entry) -> u64 {
+        match self {
+            Constraint::Count => 1,
+            Constraint::Bytes => file.size,
+            Constraint::PathBytes => file.name.len() as u64,
+        }
+    }
+}
+
+/// Files per chunk when no limit is given at all.
+const DEFAULT_FILE_COUNT: u64 = 1000;
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Period {
+    /// 2026-09-14
+    Day,
+    /// ISO week, 2026-W38
+    Week,
+    /// 2026-09
+    Month,
+    /// 2026
+    Year,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TypeBy {
+    /// File extension, e.g. jpg
+    Ext,
+    /// Kind guessed from the extension: images, video, audio, documents, archives, text or other
+    Kind,
+    /// MIME type detected from the file's magic bytes, e.g. image-png, or inode-symlink for symlinks kept by --symlinks store
+    Mime,
+}
+
+/// Splits the files into partitions by the `--date-buckets` and `--type-buckets` labels, then
+/// divides each partition on its own.
+pub fn divide_files(files: Vec<Entry>, args: &Args) -> io::Result<Vec<Chunk>> {
+    if args.date_buckets.is_none() && args.type_buckets.is_none() {
+        return Ok(divide_partition(files, args));
+    }
+    let mut partitions: BTreeMap<String, Vec<Entry>> = BTreeMap::new();
+    for file in files {
+        let mut labels = Vec::new();
+        if let Some(type_by) = args.type_buckets {
+            labels.push(type_label(&file, type_by)?);
+        }
+        if let Some(period) = args.date_buckets {
+            let date = OffsetDateTime::from(file.modified)
+                .to_offset(crate::offset_at(args, file.modified));
+            labels.push(period_label(date, period));
+        }
+        partitions.entry(labels.join("_")).or_default().push(file);
+    }
+    Ok(partitions
+        .into_iter()
+        .flat_map(|(label, files)| {
+            let chunks = divide_partition(files, args);
+            // A partition that fits in one chunk is named after the partition alone. Hash buckets
+            // always keep their number so that names stay stable.
+            let numbered = chunks.len() > 1 || args.hash_buckets.is_some();
+            chunks.into_iter().map(move |chunk| Chunk {
+                label: if numbered {
+                    format!("{}_{}", label, chunk.label)
+                } else {
+                    label.clone()
+                },
+                ..chunk
+            })
+        })
+        .collect())
+}
+
+fn type_label(file: &Entry, type_by: TypeBy) -> io::Result<String> {
+    let path = &file.path;
+    Ok(match type_by {
+        TypeBy::Ext => content::extension(path, content::NO_EXTENSION),
+        TypeBy::Kind => content::kind_of_extension(path).to_string(),
+        // A symlink stored as a link is archived without its target, which may not exist.
+        TypeBy::Mime if file.link.is_some() => "inode-symlink".to_string(),
+        TypeBy::Mime => content::detect_mime(path)?.replace('/', "-"),
+    })
+}
+
+fn period_label(date: OffsetDateTime, period: Period) -> String {
+    match period {
+        Period::Day => format!(
+            "{}-{:02}-{:02}",
+            date.year(),
+            u8::from(date.month()),
+            date.day()
+        ),
+        Period::Week => {
+            let (year, week, _) = date.to_iso_week_date();
+            format!("{}-W{:02}", year, week)
+        }
+        Period::Month => format!("{}-{:02}", date.year(), u8::from(date.month())),
+        Period::Year => date.year().to_string(),
+    }
+}
+
+fn divide_partition(files: Vec<Entry>, args: &Args) -> Vec<Chunk> {
+    let units = group_files(files, args);
+    if let Some(buckets) = args.hash_buckets {
+        return divide_by_hash(units, buckets, &crate::base_dir(args));
+    }
+    let chunks = if let Some(parts) = args.balance {
+        without_constraint(divide_balanced(units, parts))
+    } else if let Some(parts) = args.parts {
+        without_constraint(match args.split_by {
+            SplitBy::Count => divide_into_parts(units, parts, Constraint::Count),
+            SplitBy::Bytes => divide_into_parts(units, parts, Constraint::Bytes),
+        })
+    } else {
+        let limits = limits(args);
+        let chunks = divide_by_limits(units, &limits);
+        match args.min_fill {
+            Some(min_fill) => fill_last_chunk(chunks, &limits, min_fill, args.fill_tolerance),
+            None => chunks,
+        }
+    };
+    chunks
+        .into_iter()
+        .enumerate()
+        .map(|(i, (files, closed_by))| Chunk {
+            label: i.to_string(),
+            files,
+            closed_by,
+        })
+        .collect()
+}
+
+fn without_constraint(chunks: Vec<Vec<Entry>>) -> Vec<(Vec<Entry>, Option<Constraint>)> {
+    chunks.into_iter().map(|files| (files, None)).collect()
+}
+
+fn limits(args: &Args) -> Vec<(Constraint, u64)> {
+    let mut limits = Vec::new();
+    if let Some(file_count) = args.file_count_per_file {
+        limits.push((Constraint::Count, file_count));
+    }
+    if let Some(max_bytes) = args.max_bytes {
+        limits.push((Constraint::Bytes, max_bytes));
+    }
+    if let Some(max_path_bytes) = args.max_path_bytes {
+        limits.push((Constraint::PathBytes, max_path_bytes));
+    }
+    if limits.is_empty() {
+        limits.push((Constraint::Count, DEFAULT_FILE_COUNT));
+    }
+    limits
+}
+
+fn weight_of(unit: &[Entry], constraint: Constraint) -> u64 {
+    unit.iter().map(|file| constraint.weight(file)).sum()
+}
+
+/// Turns the file list into units, keeping the order in which each unit is first seen.
+/// Files without a key, and every file when no grouping option is given, are units of their own.
+fn group_files(files: Vec<Entry>, args: &Args) -> Vec<Unit> {
+    let base = crate::base_dir(args);
+    let key_of = |path: &Path| -> Option<String> {
+        if let Some(depth) = args.group_by_dir {
+            return dir_key(path, &base, depth);
+        }
+        group_key(args.group_key.as_ref()?, path)
+    };
+    let mut units: Vec<Unit> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    for mut file in files {
+        file.key = key_of(&file.path);
+        match &file.key {
+            Some(key) => match index.get(key) {
+                Some(&i) => units[i].push(file),
+                None => {
+                    index.insert(key.clone(), units.len());
+                    units.push(vec![file]);
+                }
+            },
+            None => units.push(vec![file]),
+        }
+    }
+    units
+}
+
+/// The first capture group of `--group-key` in `path`, or the whole match when the regex has
+/// no group or the group did not take part in the match.
+fn group_key(regex: &Regex, path: &Path) -> Option<String> {
+    let path = path.to_string_lossy();
+    let captures = regex.captures(&path)?;
+    captures
+        .get(1)
+        .or_else(|| captures.get(0))
+        .map(|m| m.as_str().to_string())
+}
+
+/// The directory `depth` levels below `base` that contains `path`, if `path` is that deep.
+fn dir_key(path: &Path, base: &Path, depth: usize) -> Option<String> {
+    let relative = crate::relative_path(path, base)?;
+    let components = relative.components().collect::<Vec<_>>();
+    if components.len() <= depth {
+        return None;
+    }
+    let dir = components[..depth].iter().collect::<PathBuf>();
+    Some(dir.to_string_lossy().into_owned())
+}
+
+/// Fills each chunk in order until the next unit would push it over any of `limits`, and
+/// records the first limit that would have been exceeded.
+/// A unit that exceeds a limit on its own starts a new chunk and is split file by file, its
+/// files filling chunks like units of their own. A single file that exceeds a limit gets a
+/// chunk to itself.
+fn divide_by_limits(
+    units: Vec<Unit>,
+    limits: &[(Constraint, u64)],
+) -> Vec<(Vec<Entry>, Option<Constraint>)> {
+    let mut chunks = Vec::new();
+    let mut chunk = Vec::new();
+    let mut totals = vec![0; limits.len()];
+    // A stack, so that the files of a split unit come right after it.
+    let mut pending = units;
+    pending.reverse();
+    while let Some(unit) = pending.pop() {
+        let weights = limits
+            .iter()
+            .map(|&(constraint, _)| weight_of(&unit, constraint))
+            .collect::<Vec<_>>();
+        let closed_by = exceeded(limits, &totals, &weights);
+        if !chunk.is_empty() && closed_by.is_some() {
+            chunks.push((mem::take(&mut chunk), closed_by));
+            totals.fill(0);
+        }
+        if unit.len() > 1 && exceeded(limits, &totals, &weights).is_some() {
+            pending.extend(unit.into_iter().rev().map(|file| vec![file]));
+            continue;
+        }
+        for (total, weight) in totals.iter_mut().zip(&weights) {
+            *total += weight;
+        }
+        chunk.extend(unit);
+    }
+    if !chunk.is_empty() {
+        chunks.push((chunk, None));
+    }
+    chunks
+}
+
+fn exceeded(limits: &[(Constraint, u64)], totals: &[u64], weights: &[u64]) -> Option<Constraint> {
+    limits
+        .iter()
+        .zip(totals.iter().zip(weights))
+        .find(|((_, max), (total, weight))| *total + *weight > *max)
+        .map(|((constraint, _), _)| *constraint)
+}
+
+/// The least a trailing chunk must hold before `--min-fill` redistributes it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MinFill {
+    /// Percentage of the limits
+    Percent(u64),
+    /// Number of files
+    Files(usize),
+}
+
+/// If the last chunk holds less than `min_fill`, re-divides it together with the fewest
+/// chunks before it that can take its files, so that they all stay within their limits
+/// plus `tolerance` percent. Files keep their order and units stay whole. The re-divided chunks
+/// were not closed by a limit, so they record none. When no such redistribution exists the
+/// chunks are returned unchanged and a notice is printed.
+fn fill_last_chunk(
+    mut chunks: Vec<(Vec<Entry>, Option<Constraint>)>,
+    limits: &[(Constraint, u64)],
+    min_fill: MinFill,
+    tolerance: u64,
+) -> Vec<(Vec<Entry>, Option<Constraint>)> {
+    // A single chunk has no chunks before it to take its files.
+    if chunks.len() < 2 {
+        return chunks;
+    }
+    let (last, _) = &chunks[chunks.len() - 1];
+    let undersized = match min_fill {
+        MinFill::Percent(percent) => limits
+            .iter()
+            .all(|&(constraint, max)| weight_of(last, constraint) * 100 < max * percent),
+        MinFill::Files(files) => last.len() < files,
+    };
+    if !undersized {
+        return chunks;
+    }
+    let within_tolerance = |files: &Vec<Entry>| {
+        limits
+            .iter()
+            .all(|&(constraint, max)| weight_of(files, constraint) * 100 <= max * (100 + tolerance))
+    };
+    for k in 1..chunks.len() {
+        let tail = &chunks[chunks.len() - k - 1..];
+        let units = regroup(tail.iter().flat_map(|(files, _)| files.iter().cloned()));
+        let redivided = divide_into_parts(units, k, limits[0].0);
+        if redivided.len() == k && redivided.iter().all(within_tolerance) {
+            chunks.truncate(chunks.len() - k - 1);
+            chunks.extend(without_constraint(redivided));
+            return chunks;
+        }
+    }
+    println!(
+        "--min-fill had no effect: the last {} files do not fit into the files before them within --fill-tolerance {}%",
+        chunks[chunks.len() - 1].0.len(),
+        tolerance
+    );
+    chunks
+}
+
+/// Rebuilds units from files in chunk order, where the files of a unit are adjacent.
+fn regroup(files: impl Iterator<Item = Entry>) -> Vec<Unit> {
+    let mut units: Vec<Unit> = Vec::new();
+    for file in files {
+        match units.last_mut() {
+            Some(unit) if file.key.is_some() && unit[0].key == file.key => unit.push(file),
+            _ => units.push(vec![file]),
+        }
+    }
+    units
+}
+
+/// Largest-first greedy packing: every unit goes to the part with the smallest total so far.
+/// Units keep their original relative order inside each part, and parts left empty are dropped.
+fn divide_balanced(units: Vec<Unit>, parts: usize) -> Vec<Vec<Entry>> {
+    let mut order = (0..units.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&i| Reverse(weight_of(&units[i], Constraint::Bytes)));
+    let mut totals = vec![0u64; parts.clamp(1, units.len().max(1))];
+    let mut assigned = vec![Vec::new(); totals.len()];
+    for i in order {
+        let part = (0..totals.len()).min_by_key(|&p| totals[p]).unwrap();
+        totals[part] += weight_of(&units[i], Constraint::Bytes);
+        assigned[part].push(i);
+    }
+    let mut units = units.into_iter().map(Some).collect::<Vec<_>>();
+    assigned
+        .into_iter()
+        .filter(|indices| !indices.is_empty())
+        .map(|mut indices| {
+            indices.sort_unstable();
+            indices
+                .into_iter()
+                .flat_map(|i| units[i].take().unwrap())
+                .collect()
+        })
+        .collect()
+}
+
+/// Splits the units in order into `parts` chunks, aiming each chunk at an equal share of the
+/// weight that remains. A unit is added while it brings the chunk closer to its share.
+fn divide_into_parts(units: Vec<Unit>, parts: usize, weight: Constraint) -> Vec<Vec<Entry>> {
+    let parts = parts.clamp(1, units.len().max(1));
+    let mut remaining_weight = units
+        .iter()
+        .map(|unit| weight_of(unit, weight))
+        .sum::<u64>();
+    let mut remaining_units = units.len();
+    let mut chunks = Vec::new();
+    let mut chunk = Vec::new();
+    let mut chunk_weight = 0;
+    for unit in units {
+        let unit_weight = weight_of(&unit, weight);
+        let remaining_parts = parts - chunks.len();
+        // |P * (c + w) - R| <= |P * c - R| avoids rounding the share R / P.
+        let (p, r) = (remaining_parts as i128, remaining_weight as i128);
+        let (c, w) = (chunk_weight as i128, unit_weight as i128);
+        let closer = (p * (c + w) - r).abs() <= (p * c - r).abs();
+        let must_leave = remaining_units < remaining_parts;
+        if !chunk.is_empty() && remaining_parts > 1 && (!closer || must_leave) {
+            remaining_weight -= chunk_weight;
+            chunks.push(mem::take(&mut chunk));
+            chunk_weight = 0;
+        }
+        chunk_weight += unit_weight;
+        remaining_units -= 1;
+        chunk.extend(unit);
+    }
+    if !chunk.is_empty() {
+        chunks.push(chunk);
+    }
+    chunks
+}
+
+/// Assigns every unit to bucket `hash % buckets`, hashing its group key or else the path
+/// relative to `base`. The label is the bucket number, so a bucket keeps its name across runs
+/// and empty buckets are simply not written.
+fn divide_by_hash(units: Vec<Unit>, buckets: u64, base: &Path) -> Vec<Chunk> {
+    let mut assigned: BTreeMap<u64, Vec<Entry>> = BTreeMap::new();
+    for unit in units {
+        let name = match &unit[0].key {
+            Some(key) => key.clone(),
+            None => relative_name(&unit[0].path, base),
+        };
+        let bucket = fnv1a(name.as_bytes()) % buckets;
+        assigned.entry(bucket).or_default().extend(unit);
+    }
+    assigned
+        .into_iter()
+        .map(|(bucket, files)| Chunk {
+            label: bucket.to_string(),
+            files,
+            closed_by: None,
+        })
+        .collect()
+}
+
+/// `path` relative to `base`, with `/` separators on every platform.
+fn relative_name(path: &Path, base: &Path) -> String {
+    crate::relative_path(path, base)
+        .unwrap_or_else(|| path.to_path_buf())
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// 64-bit FNV-1a. Unlike `DefaultHasher` its output is fixed, which keeps bucket numbers stable
+/// between releases.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    fn key(regex: &str, path: &str) -> Option<String> {
+        group_key(&Regex::new(regex).unwrap(), Path::new(path))
+    }
+
+    #[test]
+    fn group_key_takes_the_first_capture_group() {
+        assert_eq!(
+            key(r"IMG_(\d+)_", "a/IMG_0042_x.jpg"),
+            Some("0042".to_string())
+        );
+        assert_eq!(key(r"(\w+)-(\d+)", "shot-7.raw"), Some("shot".to_string()));
+    }
+
+    #[test]
+    fn group_key_falls_back_to_the_whole_match() {
+        assert_eq!(key(r"\d{4}", "2026/03/a.jpg"), Some("2026".to_string()));
+        assert_eq!(key(r"x(y)?z", "xz.txt"), Some("xz".to_string()));
+        assert_eq!(key(r"\d{4}", "none.txt"), None);
+    }
+
+    #[test]
+    fn group_key_honors_lazy_quantifiers() {
+        assert_eq!(key(r"^(.+?)_", "a_b_c.jpg"), Some("a".to_string()));
+        assert_eq!(key(r"^(.+)_", "a_b_c.jpg"), Some("a_b".to_string()));
+    }
+
+    fn file(i: usize, size: u64) -> Entry {
+        Entry {
+            path: PathBuf::from(format!("{}.txt", i)),
+            name: format!("{}.txt", i),
+            size,
+            modified: std::time::UNIX_EPOCH,
+            created: std::time::UNIX_EPOCH,
+            key: None,
+            link: None,
+        }
+    }
+
+    fn files(count: usize) -> Vec<Entry> {
+        (0..count).map(|i| file(i, 1)).collect()
+    }
+
+    /// One unit per size, each a single file.
+    fn units(sizes: &[u64]) -> Vec<Unit> {
+        sizes
+            .iter()
+            .enumerate()
+            .map(|(i, &size)| vec![file(i, size)])
+            .collect()
+    }
+
+    fn shape(chunks: &[(Vec<Entry>, Option<Constraint>)]) -> Vec<(Vec<u64>, Option<Constraint>)> {
+        chunks
+            .iter()
+            .map(|(files, closed_by)| (files.iter().map(|f| f.size).collect(), *closed_by))
+            .collect()
+    }
+
+    #[test]
+    fn chunks_close_before_the_limit_is_exceeded() {
+        let chunks = divide_by_limits(units(&[3, 4, 3, 5, 5]), &[(Constraint::Bytes, 10)]);
+        assert_eq!(
+            shape(&chunks),
+            [(vec![3, 4, 3], Some(Constraint::Bytes)), (vec![5, 5], None)]
+        );
+    }
+
+    #[test]
+    fn a_file_larger_than_the_limit_is_stored_alone() {
+        let chunks = divide_by_limits(units(&[4, 4, 25, 3, 3]), &[(Constraint::Bytes, 10)]);
+        assert_eq!(
+            shape(&chunks),
+            [
+                (vec![4, 4], Some(Constraint::Bytes)),
+                (vec![25], Some(Constraint::Bytes)),
+                (vec![3, 3], None)
+            ]
+        );
+        let first = divide_by_limits(units(&[25, 3]), &[(Constraint::Bytes, 10)]);
+        assert_eq!(
+            shape(&first),
+            [(vec![25], Some(Constraint::Bytes)), (vec![3], None)]
+        );
+    }
+
+    #[test]
+    fn a_split_unit_leaves_room_for_the_units_after_it() {
+        let mut units = units(&[1, 2, 3]);
+        units.insert(1, (10..15).map(|i| file(i, 10)).collect());
+        let chunks = divide_by_limits(units, &[(Constraint::Count, 4)]);
+        assert_eq!(
+            shape(&chunks),
+            [
+                (vec![1], Some(Constraint::Count)),
+                (vec![10, 10, 10, 10], Some(Constraint::Count)),
+                (vec![10, 2, 3], None)
+            ]
+        );
+    }
+
+    #[test]
+    fn the_first_limit_exceeded_closes_the_chunk() {
+        let limits = [(Constraint::Count, 2), (Constraint::Bytes, 10)];
+        let chunks = divide_by_limits(units(&[1, 1, 5, 6, 1]), &limits);
+        assert_eq!(
+            shape(&chunks),
+            [
+                (vec![1, 1], Some(Constraint::Count)),
+                (vec![5], Some(Constraint::Bytes)),
+                (vec![6, 1], None)
+            ]
+        );
+    }
+
+    fn sizes(chunks: &[Vec<Entry>]) -> Vec<Vec<u64>> {
+        chunks
+            .iter()
+            .map(|files| files.iter().map(|f| f.size).collect())
+            .collect()
+    }
+
+    #[test]
+    fn balance_gives_each_unit_to_the_lightest_part() {
+        let chunks = divide_balanced(units(&[5, 1, 4, 2]), 2);
+        assert_eq!(sizes(&chunks), [vec![5, 1], vec![4, 2]]);
+    }
+
+    #[test]
+    fn balance_makes_no_more_parts_than_units() {
+        let chunks = divide_balanced(units(&[5, 1, 4]), 100_000_000_000);
+        assert_eq!(sizes(&chunks), [vec![5], vec![4], vec![1]]);
+        assert!(divide_balanced(Vec::new(), 3).is_empty());
+    }
+
+    #[test]
+    fn parts_by_count_take_files_while_closer_to_the_share() {
+        let chunks = divide_into_parts(units(&[1; 5]), 2, Constraint::Count);
+        assert_eq!(sizes(&chunks), [vec![1, 1, 1], vec![1, 1]]);
+        let chunks = divide_into_parts(units(&[1; 7]), 3, Constraint::Count);
+        assert_eq!(sizes(&chunks), [vec![1, 1], vec![1, 1, 1], vec![1, 1]]);
+    }
+
+    #[test]
+    fn parts_by_bytes_follow_the_sizes() {
+        let chunks = divide_into_parts(units(&[8, 1, 1, 1, 1, 4]), 2, Constraint::Bytes);
+        assert_eq!(sizes(&chunks), [vec![8], vec![1, 1, 1, 1, 4]]);
+    }
+
+    #[test]
+    fn every_part_gets_a_file() {
+        let chunks = divide_into_parts(units(&[1, 1, 10]), 3, Constraint::Bytes);
+        assert_eq!(sizes(&chunks), [vec![1], vec![1], vec![10]]);
+    }
+
+    #[test]
+    fn parts_beyond_the_number_of_files_are_dropped() {
+        let chunks = divide_into_parts(units(&[1, 2, 3]), 5, Constraint::Count);
+        assert_eq!(sizes(&chunks), [vec![1], vec![2], vec![3]]);
+        assert!(divide_into_parts(Vec::new(), 5, Constraint::Count).is_empty());
+    }
+
+    #[test]
+    fn hash_buckets_are_numbered_by_bucket() {
+        let paths = ["a.jpg", "b.jpg", "c/d.jpg"];
+        let units = paths
+            .map(|path| {
+                vec![Entry {
+                    path: PathBuf::from(path),
+                    ..file(0, 1)
+                }]
+            })
+            .to_vec();
+        let chunks = divide_by_hash(units.clone(), u64::MAX, Path::new(""));
+        let mut buckets = paths.map(|path| fnv1a(path.as_bytes()) % u64::MAX);
+        buckets.sort_unstable();
+        assert_eq!(
+            chunks
+                .iter()
+                .map(|chunk| chunk.label.clone())
+                .collect::<Vec<_>>(),
+            buckets.map(|bucket| bucket.to_string())
+        );
+        let chunks = divide_by_hash(units, 1, Path::new(""));
+        assert_eq!(chunks.len(), 1);
+        assert_eq!((chunks[0].label.as_str(), chunks[0].files.len()), ("0", 3));
+    }
+
+    #[test]
+    fn fnv1a_is_fixed() {
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+    }
+
+    fn utc(year: i32, month: u8, day: u8) -> OffsetDateTime {
+        let month = time::Month::try_from(month).unwrap();
+        time::Date::from_calendar_date(year, month, day)
+            .unwrap()
+            .midnight()
+            .assume_utc()
+    }
+
+    #[test]
+    fn period_labels_are_zero_padded() {
+        let date = utc(2026, 3, 7);
+        assert_eq!(period_label(date, Period::Day), "2026-03-07");
+        assert_eq!(period_label(date, Period::Week), "2026-W10");
+        assert_eq!(period_label(date, Period::Month), "2026-03");
+        assert_eq!(period_label(date, Period::Year), "2026");
+    }
+
+    #[test]
+    fn iso_weeks_belong_to_the_year_of_their_thursday() {
+        assert_eq!(period_label(utc(2024, 12, 30), Period::Week), "2025-W01");
+        assert_eq!(period_label(utc(2026, 1, 1), Period::Week), "2026-W01");
+        assert_eq!(period_label(utc(2026, 12, 31), Period::Week), "2026-W53");
+        assert_eq!(period_label(utc(2027, 1, 3), Period::Week), "2026-W53");
+        assert_eq!(period_label(utc(2027, 1, 4), Period::Week), "2027-W01");
+    }
+
+    #[test]
+    fn date_buckets_follow_daylight_saving_time() {
+        let args = crate::parse_args([
+            "divisioner",
+            "*",
+            "out",
+            "--date-buckets",
+            "month",
+            "--timezone",
+            "CET-1CEST,M3.5.0,M10.5.0/3",
+        ]);
+        // 22:30 UTC on the last day of a month is 23:30 in winter but 00:30 the next day in summer.
+        let files = ["2026-01-31T22:30:00", "2026-07-31T22:30:00"].map(|time| Entry {
+            modified: crate::parse_date(time).unwrap().into(),
+            ..file(0, 1)
+        });
+        let chunks = divide_files(files.to_vec(), &args).unwrap();
+        assert_eq!(
+            chunks
+                .iter()
+                .map(|chunk| chunk.label.as_str())
+                .collect::<Vec<_>>(),
+            ["2026-01", "2026-08"]
+        );
+    }
+
+    #[test]
+    fn stored_symlinks_get_a_mime_label_without_opening_the_target() {
+        let args = crate::parse_args(["divisioner", "*", "out", "--type-buckets", "mime"]);
+        let link = Entry {
+            path: PathBuf::from("dangling.jpg"),
+            link: Some(PathBuf::from("no such target")),
+            ..file(0, 14)
+        };
+        let chunks = divide_files(vec![link], &args).unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].label, "inode-symlink");
+    }
+
+    #[test]
+    fn redistributed_chunks_are_not_closed_by_a_limit() {
+        let limits = [(Constraint::Count, 10)];
+        let chunks = divide_by_limits(files(34).into_iter().map(|f| vec![f]).collect(), &limits);
+        let filled = fill_last_chunk(chunks, &limits, MinFill::Percent(50), 20);
+        let shape = filled
+            .iter()
+            .map(|(files, closed_by)| (files.len(), *closed_by))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            shape,
+            [(10, Some(Constraint::Count)), (12, None), (12, None)]
+        );
+    }
+
+    #[test]
+    fn min_fill_leaves_chunks_alone_beyond_the_tolerance() {
+        let limits = [(Constraint::Count, 10)];
+        let chunks = divide_by_limits(files(34).into_iter().map(|f| vec![f]).collect(), &limits);
+        let filled = fill_last_chunk(chunks, &limits, MinFill::Percent(50), 5);
+        assert_eq!(
+            filled.iter().map(|(f, _)| f.len()).collect::<Vec<_>>(),
+            [10, 10, 10, 4]
+        );
+    }
+
+    #[test]
+    fn min_fill_leaves_a_single_chunk_alone() {
+        let limits = [(Constraint::Count, 10)];
+        let chunks = divide_by_limits(units(&[1; 3]), &limits);
+        let filled = fill_last_chunk(chunks, &limits, MinFill::Percent(50), 20);
+        assert_eq!(shape(&filled), [(vec![1, 1, 1], None)]);
+    }
+
+    #[test]
+    fn invalid_group_key_is_rejected() {
+        for regex in ["(unclosed", "*start"] {
+            let args = Args::try_parse_from(["divisioner", "*", "out", "--group-key", regex]);
+            assert!(args.is_err());
+        }
+    }
+}
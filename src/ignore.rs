@@ -0,0 +1,250 @@
+//! Directory traversal that honors `.gitignore`, `.ignore` and `.divisionerignore` files with
+//! gitignore semantics: `#` comments, `!` negation, a trailing `/` for directories only, and
+//! patterns containing a `/` anchored to the folder of the file that defines them.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use glob::{MatchOptions, Pattern};
+
+/// Ignore files read in every folder, in this order, so later files override earlier ones.
+const IGNORE_FILES: &[&str] = &[".gitignore", ".ignore", ".divisionerignore"];
+
+struct Rule {
+    /// The folder of the ignore file the rule comes from
+    dir: PathBuf,
+    pattern: Pattern,
+    negated: bool,
+    dir_only: bool,
+}
+
+/// Lists every file below `base` in name order. With `honor_ignore_files`, files that an ignore
+/// file excludes are left out, and ignored folders and `.git` folders are not entered at all.
+/// Folders that cannot be read are added to `skipped` with the reason and left out.
+pub fn walk(
+    base: &Path,
+    honor_ignore_files: bool,
+    skipped: &mut Vec<(PathBuf, String)>,
+) -> Vec<PathBuf> {
+    let mut walk = Walk {
+        honor_ignore_files,
+        rules: Vec::new(),
+        files: Vec::new(),
+        skipped,
+    };
+    walk.walk_dir(base);
+    walk.files
+}
+
+struct Walk<'a> {
+    honor_ignore_files: bool,
+    rules: Vec<Rule>,
+    files: Vec<PathBuf>,
+    skipped: &'a mut Vec<(PathBuf, String)>,
+}
+
+impl Walk<'_> {
+    fn walk_dir(&mut self, dir: &Path) {
+        let inherited = self.rules.len();
+        if let Err(e) = self.read_dir(dir) {
+            self.skipped.push((dir.to_path_buf(), e.to_string()));
+        }
+        self.rules.truncate(inherited);
+    }
+
+    fn read_dir(&mut self, dir: &Path) -> io::Result<()> {
+        let fs_dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        if self.honor_ignore_files {
+            for name in IGNORE_FILES {
+                match fs::read_to_string(fs_dir.join(name)) {
+                    Ok(text) => self.rules.extend(parse_rules(dir, &text)),
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        let mut entries = fs::read_dir(fs_dir)?.collect::<Result<Vec<_>, _>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let path = dir.join(entry.file_name());
+            let is_dir = entry.file_type()?.is_dir();
+            let ignored = self.honor_ignore_files
+                && (is_ignored(&self.rules, &path, is_dir)
+                    || (is_dir && entry.file_name() == ".git"));
+            if ignored {
+                continue;
+            }
+            if is_dir {
+                self.walk_dir(&path);
+            } else {
+                self.files.push(path);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_rules(dir: &Path, text: &str) -> Vec<Rule> {
+    text.lines()
+        .filter_map(|line| {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                return None;
+            }
+            let (negated, line) = match line.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, line.strip_prefix('\\').unwrap_or(line)),
+            };
+            let (dir_only, line) = match line.strip_suffix('/') {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            // Without an inner or leading slash a pattern matches at any depth.
+            let anchored = line.contains('/');
+            let line = line.strip_prefix('/').unwrap_or(line);
+            let glob = if anchored {
+                line.to_string()
+            } else {
+                format!("**/{}", line)
+            };
+            Some(Rule {
+                dir: dir.to_path_buf(),
+                pattern: Pattern::new(&glob).ok()?,
+                negated,
+                dir_only,
+            })
+        })
+        .collect()
+}
+
+/// The last rule that matches decides, as in git.
+fn is_ignored(rules: &[Rule], path: &Path, is_dir: bool) -> bool {
+    let options = MatchOptions {
+        case_sensitive: true,
+        require_literal_separator: true,
+        require_literal_leading_dot: false,
+    };
+    rules
+        .iter()
+        .rev()
+        .filter(|rule| is_dir || !rule.dir_only)
+        .find(|rule| {
+            path.strip_prefix(&rule.dir)
+                .is_ok_and(|relative| rule.pattern.matches_path_with(relative, options))
+        })
+        .is_some_and(|rule| !rule.negated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(files: &[(&str, &str)]) -> Vec<Rule> {
+        files
+            .iter()
+            .flat_map(|(dir, text)| parse_rules(Path::new(dir), text))
+            .collect()
+    }
+
+    fn ignored(rules: &[Rule], path: &str) -> bool {
+        is_ignored(rules, Path::new(path), false)
+    }
+
+    #[test]
+    fn patterns_without_a_slash_match_at_any_depth() {
+        let rules = rules(&[("", "*.log\nbuild")]);
+        assert!(ignored(&rules, "a.log"));
+        assert!(ignored(&rules, "src/deep/a.log"));
+        assert!(ignored(&rules, "src/build"));
+        assert!(!ignored(&rules, "a.log.txt"));
+    }
+
+    #[test]
+    fn patterns_with_a_slash_are_anchored_to_their_folder() {
+        let rules = rules(&[("", "/build\ndoc/*.txt")]);
+        assert!(ignored(&rules, "build"));
+        assert!(!ignored(&rules, "src/build"));
+        assert!(ignored(&rules, "doc/a.txt"));
+        assert!(!ignored(&rules, "src/doc/a.txt"));
+        assert!(!ignored(&rules, "doc/sub/a.txt"));
+    }
+
+    #[test]
+    fn a_trailing_slash_matches_directories_only() {
+        let rules = rules(&[("", "cache/")]);
+        assert!(is_ignored(&rules, Path::new("cache"), true));
+        assert!(is_ignored(&rules, Path::new("src/cache"), true));
+        assert!(!is_ignored(&rules, Path::new("cache"), false));
+    }
+
+    #[test]
+    fn negated_patterns_re_include_and_the_last_match_decides() {
+        let overridden = rules(&[("", "!keep.log\n*.log")]);
+        assert!(ignored(&overridden, "keep.log"));
+        let rules = rules(&[("", "*.log\n!keep.log")]);
+        assert!(ignored(&rules, "a.log"));
+        assert!(!ignored(&rules, "keep.log"));
+        assert!(!ignored(&rules, "src/keep.log"));
+    }
+
+    #[test]
+    fn comments_and_escapes() {
+        let rules = rules(&[("", "# a comment\n\\#notes\n\\!bang\n\ntrailing.txt   ")]);
+        assert_eq!(rules.len(), 3);
+        assert!(ignored(&rules, "#notes"));
+        assert!(!ignored(&rules, "# a comment"));
+        assert!(ignored(&rules, "!bang"));
+        assert!(ignored(&rules, "trailing.txt"));
+    }
+
+    #[test]
+    fn nested_ignore_files_override_their_parents() {
+        let rules = rules(&[("", "*.log\n/top.txt"), ("sub", "!*.log\n*.txt")]);
+        assert!(ignored(&rules, "a.log"));
+        assert!(!ignored(&rules, "sub/a.log"));
+        assert!(!ignored(&rules, "sub/deep/a.log"));
+        assert!(ignored(&rules, "sub/a.txt"));
+        assert!(!ignored(&rules, "a.txt"));
+        assert!(ignored(&rules, "top.txt"));
+    }
+
+    #[test]
+    fn walk_applies_an_ignore_file_only_below_its_folder() {
+        let base = std::env::temp_dir().join(format!("divisioner-ignore-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+        for dir in ["a/.git", "b", "c"] {
+            fs::create_dir_all(base.join(dir)).unwrap();
+        }
+        for (file, text) in [
+            (".gitignore", "*.tmp\n"),
+            ("a/.ignore", "!*.tmp\n"),
+            ("a/x.tmp", ""),
+            ("a/.git/config", ""),
+            ("b/y.tmp", ""),
+            ("b/y.txt", ""),
+            ("c/.divisionerignore", "*\n"),
+            ("c/z.txt", ""),
+        ] {
+            fs::write(base.join(file), text).unwrap();
+        }
+        let mut skipped = Vec::new();
+        let files = walk(&base, true, &mut skipped);
+        let names = files
+            .iter()
+            .map(|path| {
+                path.strip_prefix(&base)
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect::<Vec<_>>();
+        fs::remove_dir_all(&base).unwrap();
+        assert_eq!(names, [".gitignore", "a/.ignore", "a/x.tmp", "b/y.txt"]);
+        assert!(skipped.is_empty());
+    }
+}
@@ -1,27 +1,160 @@
 use std::{error, fs};
+use std::collections::HashMap;
+use std::ffi::OsString;
 use std::fs::File;
-use std::io::{BufWriter, Read, Write};
-use std::path::{Path, PathBuf};
+use std::io::{BufWriter, Read};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime};
 
-use clap::Parser;
-use glob::{glob_with, MatchOptions};
+use crate::archive::{Format, Transfer};
+use crate::divide::{Chunk, MinFill, Period, TypeBy};
+use crate::sort::SortBy;
+use clap::{ArgGroup, CommandFactory, Parser, ValueEnum};
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use zip::write::FileOptions;
+use regex::Regex;
+use time::{Date, Month, OffsetDateTime, PrimitiveDateTime, Time, UtcOffset};
+use tz::TimeZone;
+
+mod archive;
+mod content;
+mod dedupe;
+mod divide;
+mod ignore;
+mod search;
+mod sort;
+mod tar;
 
 const STYLE: &str = "[{elapsed_precise} {wide_bar:.green/blue}] {pos:5}/{len:5}";
 const PROGRESS_CHARS: &str = "##-";
 
-/// This application conditionally extracts files in a target folder and stores a certain number of files in a ZIP file.
+/// This application conditionally extracts files in a target folder and stores a certain number of files in a ZIP or tar file.
 #[derive(Parser, Clone, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(group(ArgGroup::new("limits").args(["file_count_per_file", "max_bytes", "max_path_bytes"]).multiple(true)))]
+#[command(group(ArgGroup::new("division").args(["balance", "parts", "hash_buckets"]).conflicts_with("limits")))]
 struct Args {
-    /// filename pattern matching (glob)
-    pattern: String,
+    /// filename pattern matching (glob). Optional with --files-from, where it only filters the list
+    #[arg(required_unless_present = "files_from")]
+    pattern: Option<String>,
     /// Destination Folder
-    dst: String,
-    /// Number of saves per file
-    #[arg(short, long, default_value_t = 1000)]
-    file_count_per_file: i32,
+    dst: Option<String>,
+    /// Folder that --keep-paths, --group-by-dir and --hash-buckets take paths relative to [default: the pattern's base folder]
+    #[arg(long, value_name = "DIR")]
+    base_dir: Option<PathBuf>,
+    /// Store files under their path relative to the base folder instead of their file name alone
+    #[arg(long)]
+    keep_paths: bool,
+    /// Number of saves per file [default: 1000 when no other limit is given]
+    #[arg(short, long, value_parser = clap::value_parser!(u64).range(1..))]
+    file_count_per_file: Option<u64>,
+    /// Maximum total size of the files stored per file (e.g. 650M, 4G). A larger file is stored alone
+    #[arg(long, value_parser = parse_size)]
+    max_bytes: Option<u64>,
+    /// Maximum total length in bytes of the entry paths stored per file
+    #[arg(long, value_parser = parse_size)]
+    max_path_bytes: Option<u64>,
+    /// Redistribute a last file that holds less than this, as a percentage of the limits (10%) or a number of files (50), into the files before it
+    #[arg(long, value_parser = parse_min_fill, conflicts_with = "division")]
+    min_fill: Option<MinFill>,
+    /// How far, in percent, --min-fill may push the files before it over their limits
+    #[arg(long, default_value_t = 10, requires = "min_fill")]
+    fill_tolerance: u64,
+    /// Spread the files over this number of files with totals as equal in size as possible
+    #[arg(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    balance: Option<usize>,
+    /// Split the files, in order, into this number of files
+    #[arg(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    parts: Option<usize>,
+    /// Put each file into one of this many files by a stable hash of its relative path, so re-runs only change the files that gained entries
+    #[arg(long, value_name = "BUCKETS", value_parser = clap::value_parser!(u64).range(1..))]
+    hash_buckets: Option<u64>,
+    /// What --parts divides evenly
+    #[arg(long, value_enum, default_value_t = SplitBy::Count, requires = "parts")]
+    split_by: SplitBy,
+    /// Put files in separate files per modification period, named after the period (e.g. dst_2026-09.zip)
+    #[arg(long, value_enum, value_name = "PERIOD")]
+    date_buckets: Option<Period>,
+    /// Put files in separate files per type, named after the type (e.g. dst_images.zip)
+    #[arg(long, value_enum, value_name = "TYPE")]
+    type_buckets: Option<TypeBy>,
+    /// Fixed UTC offset that decides where --date-buckets periods begin and end (e.g. +09:00, -05:30). It ignores daylight saving time; use --timezone for that
+    #[arg(long, value_parser = parse_utc_offset, default_value = "+00:00", allow_hyphen_values = true, requires = "date_buckets")]
+    utc_offset: UtcOffset,
+    /// Time zone that decides where --date-buckets periods begin and end, with its offset taken at each file's time: an IANA name (e.g. Europe/Berlin) or local
+    #[arg(long, value_name = "ZONE", value_parser = parse_timezone, conflicts_with = "utc_offset", requires = "date_buckets")]
+    timezone: Option<TimeZone>,
+    /// Keep every directory this many levels below the pattern's base folder in one file
+    #[arg(long, value_name = "DEPTH", conflicts_with = "group_key")]
+    group_by_dir: Option<usize>,
+    /// Keep files whose paths give the same value for this regex (its first capture group, or the whole match) in one file
+    #[arg(long, value_name = "REGEX", value_parser = Regex::new)]
+    group_key: Option<Regex>,
+    /// Also take files matching this pattern (repeatable). A pattern, positional or not, that starts with ! leaves out the files it matches instead
+    #[arg(long, value_name = "PATTERN")]
+    include: Vec<String>,
+    /// Leave out files matching this pattern (repeatable)
+    #[arg(long, value_name = "PATTERN")]
+    exclude: Vec<String>,
+    /// Take the paths listed in this file, or in stdin for -, instead of globbing. They are only filtered when a pattern or --include is given too
+    #[arg(long, value_name = "LIST", conflicts_with = "respect_ignore")]
+    files_from: Option<String>,
+    /// The --files-from list is separated by NUL characters instead of newlines
+    #[arg(short = '0', long, requires = "files_from")]
+    null: bool,
+    /// Treat the pattern and --include as regexes matched against the paths of the files below the current folder
+    #[arg(long)]
+    regex: bool,
+    /// Walk the pattern's base folder instead of globbing, skipping what .gitignore, .ignore and .divisionerignore files exclude
+    #[arg(long)]
+    respect_ignore: bool,
+    /// Leave out files smaller than this (e.g. 10K)
+    #[arg(long, value_parser = parse_size)]
+    min_size: Option<u64>,
+    /// Leave out files larger than this (e.g. 2G)
+    #[arg(long, value_parser = parse_size)]
+    max_size: Option<u64>,
+    /// Only take files modified after this: a date (2026-01-31, 2026-01-31T12:00:00, in UTC), an age (30d, 12h, 2w) or a file whose modification time is used
+    #[arg(long, value_name = "TIME", value_parser = parse_time)]
+    newer_than: Option<SystemTime>,
+    /// Only take files modified before this, given like --newer-than
+    #[arg(long, value_name = "TIME", value_parser = parse_time)]
+    older_than: Option<SystemTime>,
+    /// What to do with directories the pattern matches
+    #[arg(long, value_enum, default_value_t = Directories::Skip)]
+    directories: Directories,
+    /// What to do with symlinks
+    #[arg(long, value_enum, default_value_t = Symlinks::Follow)]
+    symlinks: Symlinks,
+    /// Fail instead of going on when some paths cannot be read during the search
+    #[arg(long)]
+    strict: bool,
+    /// Write the paths that could not be read, with the reason, to skipped.csv
+    #[arg(long)]
+    write_skipped: bool,
+    /// Store only the first of files with identical content and list the others in results.csv
+    #[arg(long)]
+    dedupe: bool,
+    /// Order the files before dividing them (default: the order the pattern yields them)
+    #[arg(long, value_enum)]
+    sort: Option<SortBy>,
+    /// Reverse the sort order
+    #[arg(long, requires = "sort")]
+    reverse: bool,
+    /// Archive format to write
+    #[arg(long, value_enum, default_value_t = Format::Zip)]
+    format: Format,
+    /// How --format dir puts the files into their folders
+    #[arg(long, value_enum, default_value_t = Transfer::Copy)]
+    transfer: Transfer,
+    /// How zip entries are compressed
+    #[arg(long, value_enum, default_value_t = Compression::Store)]
+    compression: Compression,
+    /// Extensions and MIME types that --compression adaptive stores as they are
+    #[arg(long, value_name = "TYPES", value_delimiter = ',', default_value = content::STORE_ONLY_TYPES)]
+    store_types: Vec<String>,
+    /// Compression level: 0-9 for deflate, adaptive, tar.gz and tar.xz, 1-9 for bzip2, 1-22 for zstd and tar.zst [default: the method's own]
+    #[arg(long)]
+    level: Option<i32>,
     /// Is it case-sensitive
     #[arg(long, action = clap::ArgAction::SetFalse)]
     case_sensitive: bool,
@@ -33,79 +166,704 @@ struct Args {
     require_literal_leading_dot: bool,
 }
 
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum SplitBy {
+    /// Same number of files in each part
+    Count,
+    /// Same total size in each part
+    Bytes,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Compression {
+    /// No compression
+    Store,
+    Deflate,
+    Bzip2,
+    Zstd,
+    /// Store already compressed files (see --store-types) and deflate the rest
+    Adaptive,
+}
+
+impl Compression {
+    fn method(self) -> zip::CompressionMethod {
+        match self {
+            Compression::Store => zip::CompressionMethod::Stored,
+            Compression::Deflate | Compression::Adaptive => zip::CompressionMethod::Deflated,
+            Compression::Bzip2 => zip::CompressionMethod::Bzip2,
+            Compression::Zstd => zip::CompressionMethod::Zstd,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Compression::Store => "store",
+            Compression::Deflate => "deflate",
+            Compression::Bzip2 => "bzip2",
+            Compression::Zstd => "zstd",
+            Compression::Adaptive => "adaptive",
+        }
+    }
+
+    /// Checked before anything is written, since the zip writer only rejects a bad level
+    /// once it starts the first entry.
+    fn check_level(self, level: i32) -> Result<(), String> {
+        let levels = match self {
+            Compression::Store => return Err("store takes no compression level".to_string()),
+            Compression::Deflate | Compression::Adaptive => 0..=9,
+            Compression::Bzip2 => 1..=9,
+            Compression::Zstd => 1..=22,
+        };
+        if !levels.contains(&level) {
+            return Err(format!(
+                "{} compression level must be within {}-{}",
+                self.name(),
+                levels.start(),
+                levels.end()
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Directories {
+    /// Leave them out
+    Skip,
+    /// Take every file inside them, stored under its path from the directory's name on
+    Recurse,
+    /// Stop before writing anything
+    Error,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Symlinks {
+    /// Store what the link points to
+    Follow,
+    /// Store the link itself as a symlink entry
+    Store,
+    /// Leave them out
+    Skip,
+}
+
+#[derive(Clone, Debug)]
+struct Entry {
+    path: PathBuf,
+    /// The path the file is stored under inside its archive
+    name: String,
+    size: u64,
+    modified: SystemTime,
+    created: SystemTime,
+    /// The group the file was kept together with, if any
+    key: Option<String>,
+    /// The target, for a symlink stored as a link
+    link: Option<PathBuf>,
+}
+
+/// Parses a byte count with an optional binary unit suffix: `1024`, `650M`, `4G`, `4GiB`.
+fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let digits = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(digits);
+    let number: u64 = number.parse().map_err(|_| format!("invalid size: {}", s))?;
+    let unit = unit.to_ascii_uppercase();
+    let shift = match unit.trim_end_matches("IB").trim_end_matches('B') {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => return Err(format!("invalid size unit: {}", unit)),
+    };
+    number
+        .checked_mul(1 << shift)
+        .ok_or_else(|| format!("size too large: {}", s))
+}
+
+/// Parses an age such as `30d` (units s, m, h, d, w) counted back from now, a UTC date or
+/// date-time such as `2026-01-31` or `2026-01-31T12:00:00`, or else the path of a file whose
+/// modification time is taken.
+fn parse_time(s: &str) -> Result<SystemTime, String> {
+    if let Some(seconds) = parse_age(s) {
+        return SystemTime::now()
+            .checked_sub(Duration::from_secs(seconds))
+            .ok_or_else(|| format!("age too large: {}", s));
+    }
+    if let Some(date) = parse_date(s) {
+        return Ok(date.into());
+    }
+    fs::metadata(s)
+        .and_then(|metadata| metadata.modified())
+        .map_err(|e| format!("not an age, a date or a readable file: {} ({})", s, e))
+}
+
+fn parse_age(s: &str) -> Option<u64> {
+    let unit = match s.chars().last()? {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        'w' => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    s[..s.len() - 1].parse::<u64>().ok()?.checked_mul(unit)
+}
+
+fn parse_date(s: &str) -> Option<OffsetDateTime> {
+    let (date, clock) = s.split_once(['T', ' ']).unwrap_or((s, "00:00:00"));
+    let mut date = date.splitn(3, '-').map(str::parse::<i32>);
+    let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+    let mut clock = clock.splitn(3, ':').map(str::parse::<u8>);
+    let hour = clock.next()?.ok()?;
+    let minute = clock.next().unwrap_or(Ok(0)).ok()?;
+    let second = clock.next().unwrap_or(Ok(0)).ok()?;
+    let date = Date::from_calendar_date(
+        year,
+        Month::try_from(u8::try_from(month).ok()?).ok()?,
+        u8::try_from(day).ok()?,
+    )
+    .ok()?;
+    let time = Time::from_hms(hour, minute, second).ok()?;
+    Some(PrimitiveDateTime::new(date, time).assume_utc())
+}
+
+/// Parses `--min-fill`: a percentage such as `10%` or a plain number of files.
+fn parse_min_fill(s: &str) -> Result<MinFill, String> {
+    match s.strip_suffix('%') {
+        Some(percent) => percent
+            .trim()
+            .parse()
+            .map(MinFill::Percent)
+            .map_err(|_| format!("invalid percentage: {}", s)),
+        None => s
+            .parse()
+            .map(MinFill::Files)
+            .map_err(|_| format!("invalid number of files: {}", s)),
+    }
+}
+
+/// Parses `Z` or a `+HH:MM` / `-HH:MM` offset from UTC.
+fn parse_utc_offset(s: &str) -> Result<UtcOffset, String> {
+    if s.eq_ignore_ascii_case("z") || s.eq_ignore_ascii_case("utc") {
+        return Ok(UtcOffset::UTC);
+    }
+    let invalid = || format!("invalid UTC offset (expected e.g. +09:00): {}", s);
+    let (sign, rest) = match s.split_at_checked(1) {
+        Some(("+", rest)) => (1, rest),
+        Some(("-", rest)) => (-1, rest),
+        _ => return Err(invalid()),
+    };
+    let (hours, minutes) = rest.split_once(':').unwrap_or((rest, "0"));
+    // Digits only, so that a second sign such as `+-5` is not taken.
+    let number = |s: &str| {
+        if !s.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        s.parse::<i8>().map_err(|_| invalid())
+    };
+    UtcOffset::from_hms(sign * number(hours)?, sign * number(minutes)?, 0).map_err(|_| invalid())
+}
+
+/// Parses `local` or an IANA time zone name, looked up in the system time zone database.
+fn parse_timezone(s: &str) -> Result<TimeZone, String> {
+    let zone = if s.eq_ignore_ascii_case("local") {
+        TimeZone::local()
+    } else {
+        TimeZone::from_posix_tz(s)
+    };
+    zone.map_err(|e| format!("unknown time zone: {} ({})", s, e))
+}
+
+/// The offset from UTC at `time`: the one `--timezone` has then, or else `--utc-offset`.
+fn offset_at(args: &Args, time: SystemTime) -> UtcOffset {
+    args.timezone
+        .as_ref()
+        .and_then(|zone| {
+            let unix_time = OffsetDateTime::from(time).unix_timestamp();
+            let offset = zone.find_local_time_type(unix_time).ok()?.ut_offset();
+            UtcOffset::from_whole_seconds(offset).ok()
+        })
+        .unwrap_or(args.utc_offset)
+}
+
+/// `--base-dir`, or else the leading part of the patterns that contains no wildcards, e.g.
+/// `photos/2023` for `photos/2023/**/*.jpg`, or the folder of a pattern without wildcards.
+/// With `--include` it is the part all patterns share.
+fn base_dir(args: &Args) -> PathBuf {
+    if let Some(base) = &args.base_dir {
+        return base.clone();
+    }
+    if args.regex {
+        return PathBuf::new();
+    }
+    search::include_patterns(args)
+        .map(|pattern| {
+            let components = Path::new(pattern).components().collect::<Vec<_>>();
+            let literal = components
+                .iter()
+                .take_while(|c| {
+                    !c.as_os_str()
+                        .to_string_lossy()
+                        .contains(['*', '?', '[', '{'])
+                })
+                .count();
+            // A pattern without wildcards names the file itself, whose folder is the base.
+            let base = if literal == components.len() {
+                literal.saturating_sub(1)
+            } else {
+                literal
+            };
+            components[..base].to_vec()
+        })
+        .reduce(|common, base| {
+            common
+                .into_iter()
+                .zip(base)
+                .take_while(|(a, b)| a == b)
+                .map(|(a, _)| a)
+                .collect()
+        })
+        .unwrap_or_default()
+        .into_iter()
+        .collect()
+}
+
+/// The path a file is stored under inside its archive: its file name, or with `keep_paths` its
+/// path relative to `base` with `/` separators. Paths outside `base` are refused, since they
+/// would point out of the archive.
+fn entry_name(path: &Path, base: &Path, keep_paths: bool) -> Result<String, String> {
+    if !keep_paths {
+        return Ok(path.file_name().unwrap().to_string_lossy().into_owned());
+    }
+    let outside = || {
+        format!(
+            "{} is not inside the base folder {}",
+            path.display(),
+            base.display()
+        )
+    };
+    let relative = relative_path(path, base).ok_or_else(outside)?;
+    let mut names = Vec::new();
+    for component in relative.components() {
+        match component {
+            Component::Normal(name) => names.push(name.to_string_lossy()),
+            _ => return Err(outside()),
+        }
+    }
+    if names.is_empty() {
+        return Err(format!(
+            "{} is the base folder itself, so it has no path inside it",
+            path.display()
+        ));
+    }
+    Ok(names.join("/"))
+}
+
+/// `path` relative to `base`, if it is inside it. `.` components are ignored, and a relative
+/// path is taken from the current folder when the other one is absolute.
+fn relative_path(path: &Path, base: &Path) -> Option<PathBuf> {
+    comparable(path, base)
+        .strip_prefix(comparable(base, path))
+        .ok()
+        .map(Path::to_path_buf)
+}
+
+fn comparable(path: &Path, other: &Path) -> PathBuf {
+    let path = match std::env::current_dir() {
+        Ok(dir) if other.is_absolute() && path.is_relative() => dir.join(path),
+        _ => path.to_path_buf(),
+    };
+    path.components()
+        .filter(|c| *c != Component::CurDir)
+        .collect()
+}
+
 fn get_file_as_byte_vec(filename: PathBuf) -> Result<Vec<u8>, std::io::Error> {
     let mut f = File::open(&filename)?;
     let metadata = fs::metadata(&filename)?;
     let mut buffer = vec![0; metadata.len() as usize];
-    f.read(&mut buffer)?;
+    f.read_exact(&mut buffer)?;
     Ok(buffer)
 }
 
-fn search_files(args: Args) -> Result<Vec<PathBuf>, Box<dyn error::Error>> {
-    let mut options = MatchOptions::new();
-    options.case_sensitive = args.case_sensitive;
-    options.require_literal_leading_dot = args.require_literal_leading_dot;
-    options.require_literal_separator = args.require_literal_separator;
-    let files = glob_with(args.pattern.as_str(), options)?
-        .map(|e| e.unwrap())
-        .collect::<Vec<_>>();
-    Ok(files)
+/// Fails when two files of one `--format dir` chunk would go to the same path in its folder.
+/// Checked before anything is written, since `--transfer move` cannot be undone halfway.
+fn check_names(chunks: &[Chunk], args: &Args) -> Result<(), String> {
+    if args.format != Format::Dir {
+        return Ok(());
+    }
+    for chunk in chunks {
+        let mut names = HashMap::new();
+        for file in &chunk.files {
+            if let Some(other) = names.insert(file.name.as_str(), &file.path) {
+                return Err(format!(
+                    "{} and {} would both be stored as {} in chunk {}{}",
+                    other.display(),
+                    file.path.display(),
+                    file.name,
+                    chunk.label,
+                    if args.keep_paths {
+                        ""
+                    } else {
+                        "; pass --keep-paths to keep their folders apart"
+                    }
+                ));
+            }
+        }
+    }
+    Ok(())
 }
 
-fn divide_files(files: Vec<PathBuf>, file_count: usize) -> Vec<Vec<PathBuf>> {
-    files
-        .chunks(file_count)
-        .map(|chunk| chunk.to_vec())
-        .collect()
+/// Parses the command line. With `--files-from` the pattern may be left out, and a single
+/// positional argument is then the destination.
+fn parse_args<T: Into<OsString> + Clone>(command_line: impl IntoIterator<Item = T>) -> Args {
+    let mut args = Args::parse_from(command_line);
+    if args.dst.is_none() && args.files_from.is_some() {
+        args.dst = args.pattern.take();
+    }
+    if args.dst.is_none() {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::MissingRequiredArgument,
+                "the destination folder is required",
+            )
+            .exit();
+    }
+    args
 }
 
 fn main() -> Result<(), Box<dyn error::Error>> {
-    let args = Args::parse();
-    let dst = Path::new(args.dst.as_str());
-    if dst.is_dir() {
-        if !dst.read_dir()?.next().is_none() {
-            println!("Destination folder is not empty.");
-            return Ok(());
+    let args = parse_args(std::env::args_os());
+    args.format.check_args(&args)?;
+    let dst = Path::new(args.dst.as_deref().expect("checked by parse_args"));
+    if dst.is_dir() && dst.read_dir()?.next().is_some() {
+        println!("Destination folder is not empty.");
+        return Ok(());
+    }
+    let (files, report) = search::search_files(args.clone())?;
+    if args.write_skipped {
+        fs::create_dir_all(dst)?;
+        let mut writer = csv::Writer::from_path(dst.join("skipped.csv"))?;
+        writer.write_record(["path", "reason"])?;
+        for (path, reason) in &report.skipped {
+            writer.write_record([path.to_string_lossy().as_ref(), reason])?;
         }
-    } else {
-        fs::create_dir_all(dst.join("zip"))?;
     }
-    let files = search_files(args.clone())?;
-    let divided_files = divide_files(files.clone(), args.file_count_per_file as usize);
+    if args.strict && !report.skipped.is_empty() {
+        report.print();
+        return Err(format!("{} paths could not be read", report.skipped.len()).into());
+    }
+    let (files, duplicates) = if args.dedupe {
+        dedupe::dedupe(files)?
+    } else {
+        (files, Vec::new())
+    };
+    let divided_files = divide::divide_files(files.clone(), &args)?;
+    check_names(&divided_files, &args)?;
+    let archive_dir = dst.join(args.format.folder());
+    fs::create_dir_all(&archive_dir)?;
     let bars = MultiProgress::new();
     let block_pb = bars.add(ProgressBar::new(divided_files.len() as u64));
     block_pb.set_style(
         ProgressStyle::default_bar()
-            .template(&*("Blocks: ".to_owned() + STYLE))?
+            .template(&("Blocks: ".to_owned() + STYLE))?
             .progress_chars(PROGRESS_CHARS),
     );
     let file_pb = bars.add(ProgressBar::new(files.len() as u64));
     file_pb.set_style(
         ProgressStyle::default_bar()
-            .template(&*("Files : ".to_owned() + STYLE))?
+            .template(&("Files : ".to_owned() + STYLE))?
             .progress_chars(PROGRESS_CHARS),
     );
     let writer = BufWriter::new(File::create(dst.join("results.csv"))?);
     let mut writer = csv::Writer::from_writer(writer);
-    writer.write_record(&["zip", "filename"])?;
-    for i in 0..divided_files.len() {
-        let block: &Vec<PathBuf> = divided_files.get(i).unwrap();
-        let filename = format!("{}_{}.zip", dst.file_name().unwrap().to_str().unwrap(), i);
-        let path = dst.join("zip").join(filename.clone());
-        let file = File::create(path)?;
-        let mut zip = zip::ZipWriter::new(file);
-        let options = FileOptions::default()
-            .compression_method(zip::CompressionMethod::Stored)
-            .unix_permissions(0o755);
+    writer.write_record(["zip", "filename", "key", "duplicate_of"])?;
+    let archives_writer = BufWriter::new(File::create(dst.join("archives.csv"))?);
+    let mut archives_writer = csv::Writer::from_writer(archives_writer);
+    archives_writer.write_record(["zip", "files", "bytes", "closed_by", "compression", "level"])?;
+    let mut archived = HashMap::new();
+    for chunk in &divided_files {
+        let block: &Vec<Entry> = &chunk.files;
+        let (archive_name, filename) = match args.format {
+            Format::Dir => {
+                let name = archive::dir_name(&chunk.label);
+                (name.clone(), name)
+            }
+            format => {
+                let name = format!("{}.{}", chunk.label, format.name());
+                let filename = format!("{}_{}", dst.file_name().unwrap().to_str().unwrap(), name);
+                (name, filename)
+            }
+        };
+        let mut archive = archive::create(&archive_dir.join(filename), &args)?;
         for item in block {
-            let item_name = String::from(item.file_name().unwrap().to_str().unwrap());
-            zip.start_file(item_name.clone(), options)?;
-            zip.write_all(&*get_file_as_byte_vec(item.clone().to_path_buf())?)?;
-            writer.write_record(&[format!("{}.zip", i), item_name])?;
+            let item_name = item.name.clone();
+            match &item.link {
+                Some(target) => archive.add_symlink(&item_name, item, target)?,
+                None => archive.add_file(&item_name, item)?,
+            }
+            writer.write_record(&[
+                archive_name.clone(),
+                item_name,
+                item.key.clone().unwrap_or_default(),
+                String::new(),
+            ])?;
+            archived.insert(&item.path, (archive_name.clone(), item));
             file_pb.inc(1);
         }
-        zip.finish()?;
+        archive.finish()?;
+        archives_writer.write_record(&[
+            archive_name,
+            block.len().to_string(),
+            block.iter().map(|item| item.size).sum::<u64>().to_string(),
+            chunk
+                .closed_by
+                .map_or("", |constraint| constraint.as_str())
+                .to_string(),
+            args.format.compression_name(&args).to_string(),
+            args.level
+                .map(|level| level.to_string())
+                .unwrap_or_default(),
+        ])?;
         block_pb.inc(1);
     }
+    for duplicate in &duplicates {
+        let (archive_name, original) = &archived[&duplicate.original];
+        writer.write_record(&[
+            archive_name.clone(),
+            duplicate.file.name.clone(),
+            String::new(),
+            original.name.clone(),
+        ])?;
+    }
+    report.print();
+    if !duplicates.is_empty() {
+        println!(
+            "Duplicates: {} files, {} bytes saved",
+            duplicates.len(),
+            duplicates
+                .iter()
+                .map(|duplicate| duplicate.file.size)
+                .sum::<u64>()
+        );
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn base(command_line: &[&str]) -> PathBuf {
+        base_dir(&parse_args(["divisioner"].iter().chain(command_line)))
+    }
+
+    #[test]
+    fn sizes_take_binary_unit_suffixes() {
+        assert_eq!(parse_size("1024"), Ok(1024));
+        assert_eq!(parse_size("650M"), Ok(650 << 20));
+        assert_eq!(parse_size("4G"), Ok(4 << 30));
+        assert_eq!(parse_size("4GiB"), Ok(4 << 30));
+        assert_eq!(parse_size("2kb"), Ok(2048));
+        assert_eq!(parse_size(" 1T "), Ok(1 << 40));
+    }
+
+    #[test]
+    fn invalid_sizes_are_rejected() {
+        assert!(parse_size("").is_err());
+        assert!(parse_size("M").is_err());
+        assert!(parse_size("1.5G").is_err());
+        assert!(parse_size("10X").is_err());
+        assert!(parse_size("-1").is_err());
+    }
+
+    #[test]
+    fn sizes_that_overflow_are_rejected() {
+        assert_eq!(parse_size("16777215T"), Ok(16777215 << 40));
+        assert_eq!(
+            parse_size("16777216T"),
+            Err("size too large: 16777216T".to_string())
+        );
+        assert!(parse_size("18446744073709551616").is_err());
+    }
+
+    #[test]
+    fn zero_counts_are_rejected() {
+        for option in ["--balance", "--parts", "--hash-buckets", "-f"] {
+            let args = Args::try_parse_from(["divisioner", "*", "out", option, "0"]);
+            assert!(args.is_err(), "{} 0 was accepted", option);
+        }
+    }
+
+    #[test]
+    fn ages_take_a_unit() {
+        assert_eq!(parse_age("90s"), Some(90));
+        assert_eq!(parse_age("5m"), Some(5 * 60));
+        assert_eq!(parse_age("12h"), Some(12 * 60 * 60));
+        assert_eq!(parse_age("30d"), Some(30 * 24 * 60 * 60));
+        assert_eq!(parse_age("2w"), Some(2 * 7 * 24 * 60 * 60));
+        for s in ["", "d", "30", "30x", "1.5h", "-1d", "30000000000000000w"] {
+            assert_eq!(parse_age(s), None, "{} was taken as an age", s);
+        }
+    }
+
+    #[test]
+    fn dates_are_utc_with_an_optional_time() {
+        let utc = |date: &str| parse_date(date).map(|date| date.unix_timestamp());
+        assert_eq!(utc("2026-01-31"), Some(1_769_817_600));
+        assert_eq!(utc("2026-01-31T12:00:00"), Some(1_769_860_800));
+        assert_eq!(utc("2026-01-31 12:30"), Some(1_769_862_600));
+        for s in [
+            "2026-01",
+            "2026-02-30",
+            "2026-13-01",
+            "2026-01-31T25:00",
+            "31-01-2026x",
+        ] {
+            assert_eq!(utc(s), None, "{} was taken as a date", s);
+        }
+    }
+
+    #[test]
+    fn times_are_ages_dates_or_files() {
+        let before = SystemTime::now() - Duration::from_secs(60 * 60);
+        let age = parse_time("1h").unwrap();
+        assert!(age >= before && age <= SystemTime::now() - Duration::from_secs(60 * 60));
+        assert_eq!(
+            parse_time("2026-01-31"),
+            Ok(SystemTime::UNIX_EPOCH + Duration::from_secs(1_769_817_600))
+        );
+        let modified = fs::metadata("Cargo.toml").unwrap().modified().unwrap();
+        assert_eq!(parse_time("Cargo.toml"), Ok(modified));
+        assert!(parse_time("no such file").is_err());
+    }
+
+    fn offset(hours: i8, minutes: i8) -> UtcOffset {
+        UtcOffset::from_hms(hours, minutes, 0).unwrap()
+    }
+
+    #[test]
+    fn utc_offsets_take_a_sign_and_optional_minutes() {
+        assert_eq!(parse_utc_offset("+09:00"), Ok(offset(9, 0)));
+        assert_eq!(parse_utc_offset("-05:30"), Ok(offset(-5, -30)));
+        assert_eq!(parse_utc_offset("+5"), Ok(offset(5, 0)));
+        assert_eq!(parse_utc_offset("Z"), Ok(UtcOffset::UTC));
+        assert_eq!(parse_utc_offset("utc"), Ok(UtcOffset::UTC));
+    }
+
+    #[test]
+    fn invalid_utc_offsets_are_rejected() {
+        for s in [
+            "", "09:00", "+", "+ab:00", "+09:60", "+26:00", "+09:-30", "+-5", "++5",
+        ] {
+            assert!(parse_utc_offset(s).is_err(), "{} was accepted", s);
+        }
+    }
+
+    #[test]
+    fn timezone_offsets_change_with_daylight_saving_time() {
+        let args = parse_args([
+            "divisioner",
+            "*",
+            "out",
+            "--date-buckets",
+            "day",
+            "--timezone",
+            "CET-1CEST,M3.5.0,M10.5.0/3",
+        ]);
+        let at = |time| offset_at(&args, parse_date(time).unwrap().into());
+        // Summer time starts at 01:00 UTC on the last Sunday of March.
+        assert_eq!(at("2026-03-29T00:59:59"), offset(1, 0));
+        assert_eq!(at("2026-03-29T01:00:00"), offset(2, 0));
+        assert_eq!(at("2026-10-25T00:59:59"), offset(2, 0));
+        assert_eq!(at("2026-10-25T01:00:00"), offset(1, 0));
+        let fixed = parse_args(["divisioner", "*", "out", "--date-buckets", "day"]);
+        assert_eq!(offset_at(&fixed, SystemTime::now()), UtcOffset::UTC);
+    }
+
+    fn chunk_of(paths: &[&str]) -> Chunk {
+        let files = paths
+            .iter()
+            .map(|path| Entry {
+                path: PathBuf::from(path),
+                name: entry_name(Path::new(path), Path::new(""), false).unwrap(),
+                size: 0,
+                modified: SystemTime::UNIX_EPOCH,
+                created: SystemTime::UNIX_EPOCH,
+                key: None,
+                link: None,
+            })
+            .collect();
+        Chunk {
+            label: "0".to_string(),
+            files,
+            closed_by: None,
+        }
+    }
+
+    #[test]
+    fn folders_refuse_two_files_with_the_same_name() {
+        let chunks = [chunk_of(&["a/f1.bin", "b/f1.bin"])];
+        let dir = parse_args(["divisioner", "*", "out", "--format", "dir"]);
+        let error = check_names(&chunks, &dir).unwrap_err();
+        assert!(error.contains("would both be stored as f1.bin in chunk 0"));
+        assert!(error.ends_with("pass --keep-paths to keep their folders apart"));
+        assert!(check_names(&[chunk_of(&["a/f1.bin", "b/f2.bin"])], &dir).is_ok());
+    }
+
+    #[test]
+    fn archives_may_hold_two_files_with_the_same_name() {
+        let chunks = [chunk_of(&["a/f1.bin", "b/f1.bin"])];
+        for format in ["zip", "tar"] {
+            let args = parse_args(["divisioner", "*", "out", "--format", format]);
+            assert!(check_names(&chunks, &args).is_ok());
+        }
+    }
+
+    #[test]
+    fn base_dir_stops_at_the_first_wildcard() {
+        assert_eq!(
+            base(&["photos/2023/**/*.jpg", "out"]),
+            Path::new("photos/2023")
+        );
+        assert_eq!(base(&["photos/{a,b}/*", "out"]), Path::new("photos"));
+        assert_eq!(
+            base(&["photos/2023/*", "out", "--include", "photos/2024/*"]),
+            Path::new("photos")
+        );
+    }
+
+    #[test]
+    fn base_dir_of_a_literal_pattern_is_its_folder() {
+        assert_eq!(base(&["src/a/f1.bin", "out"]), Path::new("src/a"));
+        assert_eq!(base(&["f1.bin", "out"]), Path::new(""));
+        assert_eq!(
+            base(&["src/a/f1.bin", "out", "--base-dir", "src"]),
+            Path::new("src")
+        );
+    }
+
+    #[test]
+    fn entry_names_are_relative_to_the_base() {
+        let path = Path::new("./photos/2023/a.jpg");
+        assert_eq!(
+            entry_name(path, Path::new("photos"), false).unwrap(),
+            "a.jpg"
+        );
+        assert_eq!(
+            entry_name(path, Path::new("photos"), true).unwrap(),
+            "2023/a.jpg"
+        );
+        assert_eq!(
+            entry_name(path, Path::new(""), true).unwrap(),
+            "photos/2023/a.jpg"
+        );
+    }
+
+    #[test]
+    fn entry_names_outside_or_at_the_base_are_refused() {
+        assert!(entry_name(Path::new("other/a.jpg"), Path::new("photos"), true).is_err());
+        assert!(entry_name(Path::new("photos/../a.jpg"), Path::new("photos"), true).is_err());
+        assert!(entry_name(Path::new("photos/a.jpg"), Path::new("photos/a.jpg"), true).is_err());
+    }
+}
@@ -0,0 +1,578 @@
+use std::collections::HashSet;
+use std::error;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use glob::{glob_with, MatchOptions, Pattern};
+use regex::{Regex, RegexBuilder};
+
+use crate::{ignore, sort, Args, Directories, Entry, Symlinks};
+
+/// What the search left out, printed once the archives are written.
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Files dropped by each `--exclude` pattern, in the order given
+    pub excluded: Vec<(String, usize)>,
+    /// Files dropped by the size and time filters
+    pub filtered: usize,
+    /// Listed by `--files-from` but not found
+    pub missing: Vec<PathBuf>,
+    /// Paths the search could not read, with the reason
+    pub skipped: Vec<(PathBuf, String)>,
+    /// Directories left out by `--directories skip`
+    pub directories: usize,
+    /// Symlinks left out by `--symlinks skip`
+    pub symlinks: usize,
+}
+
+impl Report {
+    pub fn print(&self) {
+        for (pattern, count) in &self.excluded {
+            println!("Excluded by {}: {} files", pattern, count);
+        }
+        if self.filtered > 0 {
+            println!("Filtered out by size or time: {} files", self.filtered);
+        }
+        for path in &self.missing {
+            println!("Not found: {}", path.display());
+        }
+        if self.directories > 0 {
+            println!("Skipped directories: {}", self.directories);
+        }
+        if self.symlinks > 0 {
+            println!("Skipped symlinks: {}", self.symlinks);
+        }
+        for (path, reason) in &self.skipped {
+            println!("Skipped {}: {}", path.display(), reason);
+        }
+    }
+}
+
+pub fn match_options(args: &Args) -> MatchOptions {
+    let mut options = MatchOptions::new();
+    options.case_sensitive = args.case_sensitive;
+    options.require_literal_leading_dot = args.require_literal_leading_dot;
+    options.require_literal_separator = args.require_literal_separator;
+    options
+}
+
+/// Every pattern files are searched with: the positional one followed by the `--include`s,
+/// leaving out the negated ones.
+pub fn include_patterns(args: &Args) -> impl Iterator<Item = &str> {
+    all_patterns(args).filter(|pattern| !pattern.starts_with('!'))
+}
+
+/// The positional and `--include` patterns that start with `!`, without it.
+fn negated_patterns(args: &Args) -> impl Iterator<Item = &str> {
+    all_patterns(args).filter_map(|pattern| pattern.strip_prefix('!'))
+}
+
+fn all_patterns(args: &Args) -> impl Iterator<Item = &str> {
+    args.pattern.iter().chain(&args.include).map(String::as_str)
+}
+
+pub fn search_files(args: Args) -> Result<(Vec<Entry>, Report), Box<dyn error::Error>> {
+    let options = match_options(&args);
+    let excludes = args
+        .exclude
+        .iter()
+        .map(|pattern| {
+            expand_braces(pattern)
+                .iter()
+                .map(|pattern| Pattern::new(pattern))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut report = Report {
+        excluded: args
+            .exclude
+            .iter()
+            .map(|pattern| (pattern.clone(), 0))
+            .collect(),
+        ..Report::default()
+    };
+    let base = crate::base_dir(&args);
+    let mut seen = HashSet::new();
+    let mut visited = HashSet::new();
+    let mut files = Vec::new();
+    // A stack, so that the contents of a recursed directory come right after it. Each path
+    // comes with the folder that files found by recursing are named relative to.
+    let mut pending = candidates(&args, options, &mut report)?
+        .into_iter()
+        .map(|path| (path, None))
+        .collect::<Vec<(PathBuf, Option<PathBuf>)>>();
+    pending.reverse();
+    while let Some((path, root)) = pending.pop() {
+        if !seen.insert(path.clone()) {
+            continue;
+        }
+        if let Some(i) = excludes
+            .iter()
+            .position(|exclude| exclude.iter().any(|p| p.matches_path_with(&path, options)))
+        {
+            report.excluded[i].1 += 1;
+            continue;
+        }
+        let mut metadata = match fs::symlink_metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound && args.files_from.is_some() => {
+                report.missing.push(path);
+                continue;
+            }
+            Err(e) => {
+                report.skipped.push((path, e.to_string()));
+                continue;
+            }
+        };
+        let mut link = None;
+        if metadata.is_symlink() {
+            match args.symlinks {
+                Symlinks::Skip => {
+                    report.symlinks += 1;
+                    continue;
+                }
+                Symlinks::Store => match fs::read_link(&path) {
+                    Ok(target) => link = Some(target),
+                    Err(e) => {
+                        report.skipped.push((path, e.to_string()));
+                        continue;
+                    }
+                },
+                Symlinks::Follow => match fs::metadata(&path) {
+                    Ok(target) => metadata = target,
+                    Err(e) => {
+                        let reason = match e.kind() {
+                            io::ErrorKind::NotFound => "dangling symlink".to_string(),
+                            _ if is_link_loop(&path) => "symlink loop".to_string(),
+                            _ => e.to_string(),
+                        };
+                        report.skipped.push((path, reason));
+                        continue;
+                    }
+                },
+            }
+        }
+        if metadata.is_dir() {
+            match args.directories {
+                Directories::Skip => report.directories += 1,
+                Directories::Recurse => match children(&path, &mut visited) {
+                    Ok(children) => {
+                        // Below a matched directory, files keep their path from that
+                        // directory's name on.
+                        let root =
+                            root.unwrap_or_else(|| path.parent().unwrap_or(&path).to_path_buf());
+                        pending.extend(
+                            children
+                                .into_iter()
+                                .rev()
+                                .map(|child| (child, Some(root.clone()))),
+                        )
+                    }
+                    Err(reason) => report.skipped.push((path, reason)),
+                },
+                Directories::Error => {
+                    return Err(format!("matched a directory: {}", path.display()).into())
+                }
+            }
+            continue;
+        }
+        let name = match &root {
+            Some(root) if !args.keep_paths => crate::entry_name(&path, root, true)?,
+            _ => crate::entry_name(&path, &base, args.keep_paths)?,
+        };
+        let mut file = entry(&path, &metadata, name)?;
+        if let Some(target) = link {
+            file.size = target.as_os_str().len() as u64;
+            file.link = Some(target);
+        }
+        if !passes_filters(&file, &args) {
+            report.filtered += 1;
+            continue;
+        }
+        files.push(file);
+    }
+    if let Some(sort_by) = args.sort {
+        sort::sort_files(&mut files, sort_by, args.reverse);
+    }
+    Ok((files, report))
+}
+
+/// Paths matching any include pattern, either as globbed or taken from a list that is
+/// filtered by the patterns: the `--files-from` list, which is kept whole when no pattern is
+/// given, or else with `--respect-ignore` or `--regex` the files found by walking the base
+/// folder, where `--respect-ignore` skips what ignore files exclude.
+/// Entries that cannot be read are added to `report.skipped` instead of failing the search.
+fn candidates(
+    args: &Args,
+    options: MatchOptions,
+    report: &mut Report,
+) -> Result<Vec<PathBuf>, Box<dyn error::Error>> {
+    let listed = match &args.files_from {
+        Some(list) => Some(read_list(list, args.null)?),
+        None if args.respect_ignore || args.regex => Some(ignore::walk(
+            &crate::base_dir(args),
+            args.respect_ignore,
+            &mut report.skipped,
+        )),
+        None => None,
+    };
+    let matcher = Matcher::new(args, options)?;
+    if let Some(files) = listed {
+        // A --files-from list given without any pattern is taken as it is.
+        if args.pattern.is_none() && args.include.is_empty() {
+            return Ok(files);
+        }
+        return Ok(files
+            .into_iter()
+            .filter(|path| matcher.matches(path))
+            .collect());
+    }
+    if include_patterns(args).next().is_none() {
+        return Err("patterns starting with ! need a pattern without ! to take files from".into());
+    }
+    let mut paths = Vec::new();
+    for pattern in include_patterns(args).flat_map(expand_braces) {
+        for path in glob_with(&pattern, options)? {
+            match path {
+                Ok(path) if matcher.negated.matches(&path) => {}
+                Ok(path) => paths.push(path),
+                Err(e) => report
+                    .skipped
+                    .push((e.path().to_path_buf(), e.error().to_string())),
+            }
+        }
+    }
+    Ok(paths)
+}
+
+/// The include patterns, for filtering a list of paths. Patterns starting with `!` leave out
+/// what they match.
+struct Matcher {
+    include: Patterns,
+    negated: Patterns,
+}
+
+enum Patterns {
+    Globs(Vec<Pattern>, MatchOptions),
+    /// With `--regex`
+    Regexes(Vec<Regex>),
+}
+
+impl Matcher {
+    fn new(args: &Args, options: MatchOptions) -> Result<Matcher, Box<dyn error::Error>> {
+        Ok(Matcher {
+            include: Patterns::new(include_patterns(args), args, options)?,
+            negated: Patterns::new(negated_patterns(args), args, options)?,
+        })
+    }
+
+    /// Whether `path` matches an include pattern, if there is any, and no negated one.
+    fn matches(&self, path: &Path) -> bool {
+        (self.include.is_empty() || self.include.matches(path)) && !self.negated.matches(path)
+    }
+}
+
+impl Patterns {
+    fn new<'a>(
+        patterns: impl Iterator<Item = &'a str>,
+        args: &Args,
+        options: MatchOptions,
+    ) -> Result<Patterns, Box<dyn error::Error>> {
+        if args.regex {
+            let regexes = patterns
+                .map(|pattern| {
+                    RegexBuilder::new(pattern)
+                        .case_insensitive(!options.case_sensitive)
+                        .build()
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            return Ok(Patterns::Regexes(regexes));
+        }
+        let patterns = patterns
+            .flat_map(expand_braces)
+            .map(|pattern| Pattern::new(&pattern))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Patterns::Globs(patterns, options))
+    }
+
+    fn is_empty(&self) -> bool {
+        match self {
+            Patterns::Globs(patterns, _) => patterns.is_empty(),
+            Patterns::Regexes(regexes) => regexes.is_empty(),
+        }
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        match self {
+            Patterns::Globs(patterns, options) => patterns
+                .iter()
+                .any(|pattern| pattern.matches_path_with(path, *options)),
+            Patterns::Regexes(regexes) => {
+                let path = path.to_string_lossy();
+                regexes.iter().any(|regex| regex.is_match(&path))
+            }
+        }
+    }
+}
+
+/// Expands shell-style braces: `*.{jpg,png}` becomes `*.jpg` and `*.png`. Braces may nest, and
+/// braces without a comma at their own level are kept as they are.
+pub fn expand_braces(pattern: &str) -> Vec<String> {
+    let Some((open, close, commas)) = find_braces(pattern) else {
+        return vec![pattern.to_string()];
+    };
+    let (prefix, suffix) = (&pattern[..open], &pattern[close + 1..]);
+    let mut bounds = vec![open];
+    bounds.extend(commas);
+    bounds.push(close);
+    bounds
+        .windows(2)
+        .flat_map(|pair| {
+            expand_braces(&format!(
+                "{}{}{}",
+                prefix,
+                &pattern[pair[0] + 1..pair[1]],
+                suffix
+            ))
+        })
+        .collect()
+}
+
+/// The first brace pair with a comma at its own level, with the positions of those commas.
+fn find_braces(pattern: &str) -> Option<(usize, usize, Vec<usize>)> {
+    let mut stack: Vec<(usize, Vec<usize>)> = Vec::new();
+    let mut in_class = false;
+    for (i, c) in pattern.char_indices() {
+        match c {
+            '[' if !in_class => in_class = true,
+            ']' if in_class => in_class = false,
+            _ if in_class => {}
+            '{' => stack.push((i, Vec::new())),
+            ',' => {
+                if let Some((_, commas)) = stack.last_mut() {
+                    commas.push(i);
+                }
+            }
+            '}' => {
+                if let Some((open, commas)) = stack.pop() {
+                    if !commas.is_empty() && stack.is_empty() {
+                        return Some((open, i, commas));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Reads newline or, with `null`, NUL separated paths from a file or from stdin for `-`.
+/// Empty entries are skipped.
+fn read_list(list: &str, null: bool) -> io::Result<Vec<PathBuf>> {
+    let mut data = Vec::new();
+    if list == "-" {
+        io::stdin().read_to_end(&mut data)?;
+    } else {
+        File::open(list)?.read_to_end(&mut data)?;
+    }
+    let separator = if null { b'\0' } else { b'\n' };
+    Ok(data
+        .split(|&b| b == separator)
+        .map(|entry| {
+            if null {
+                entry
+            } else {
+                entry.strip_suffix(b"\r").unwrap_or(entry)
+            }
+        })
+        .filter(|entry| !entry.is_empty())
+        .map(path_from_bytes)
+        .collect())
+}
+
+/// Any bytes make a path on unix, so names from `find -print0` arrive unchanged.
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::OsStringExt;
+
+    PathBuf::from(OsString::from_vec(bytes.to_vec()))
+}
+
+/// Elsewhere paths are Unicode, so bytes that are not UTF-8 are replaced.
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// `--min-size`, `--max-size`, `--newer-than` and `--older-than`.
+fn passes_filters(file: &Entry, args: &Args) -> bool {
+    args.min_size.is_none_or(|min| file.size >= min)
+        && args.max_size.is_none_or(|max| file.size <= max)
+        && args.newer_than.is_none_or(|time| file.modified > time)
+        && args.older_than.is_none_or(|time| file.modified < time)
+}
+
+/// Whether following `path` still ends at a symlink after as many hops as the kernel allows.
+fn is_link_loop(path: &Path) -> bool {
+    let mut current = path.to_path_buf();
+    for _ in 0..40 {
+        match fs::read_link(&current) {
+            Ok(target) => current = current.parent().unwrap_or(Path::new("")).join(target),
+            Err(_) => return false,
+        }
+    }
+    true
+}
+
+/// Whether `dir` resolves to one of its own ancestors or to a directory already recursed into.
+/// Otherwise it is recorded in `visited`.
+fn is_dir_loop(dir: &Path, visited: &mut HashSet<PathBuf>) -> io::Result<bool> {
+    let canonical = fs::canonicalize(dir)?;
+    let into_ancestor = dir
+        .parent()
+        .and_then(|parent| fs::canonicalize(parent).ok())
+        .is_some_and(|parent| parent.starts_with(&canonical));
+    Ok(into_ancestor || !visited.insert(canonical))
+}
+
+/// The contents of `dir` in name order, or why it is not recursed into.
+fn children(dir: &Path, visited: &mut HashSet<PathBuf>) -> Result<Vec<PathBuf>, String> {
+    // Following symlinks can lead back into a directory already listed.
+    if is_dir_loop(dir, visited).map_err(|e| e.to_string())? {
+        return Err("directory loop".to_string());
+    }
+    sorted_children(dir).map_err(|e| e.to_string())
+}
+
+fn sorted_children(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut children = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    children.sort();
+    Ok(children)
+}
+
+fn entry(path: &Path, metadata: &fs::Metadata, name: String) -> io::Result<Entry> {
+    let modified = metadata.modified()?;
+    Ok(Entry {
+        path: path.to_path_buf(),
+        name,
+        size: metadata.len(),
+        modified,
+        created: metadata.created().unwrap_or(modified),
+        key: None,
+        link: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matcher(args: &[&str]) -> Matcher {
+        let args = crate::parse_args(["divisioner"].iter().chain(args));
+        Matcher::new(&args, match_options(&args)).unwrap()
+    }
+
+    #[test]
+    fn regex_matches_anywhere_in_the_path() {
+        let matcher = matcher(&["--regex", r"\d{4}/[a-z]+\.jpg$", "out"]);
+        assert!(matcher.matches(Path::new("photos/2026/beach.jpg")));
+        assert!(!matcher.matches(Path::new("photos/2026/beach.jpg.bak")));
+    }
+
+    #[test]
+    fn regex_classes_follow_case_sensitivity() {
+        let pattern = r"[a-z]+\.JPG$";
+        assert!(!matcher(&["--regex", pattern, "out"]).matches(Path::new("BEACH.jpg")));
+        let insensitive = matcher(&["--regex", "--case-sensitive", pattern, "out"]);
+        assert!(insensitive.matches(Path::new("BEACH.jpg")));
+    }
+
+    #[test]
+    fn negated_patterns_leave_files_out() {
+        let globs = matcher(&["photos/**/*", "out", "--include", "!**/*.{tmp,bak}"]);
+        assert!(globs.matches(Path::new("photos/a.jpg")));
+        assert!(!globs.matches(Path::new("photos/a.tmp")));
+        assert!(!globs.matches(Path::new("photos/x/a.bak")));
+        let regexes = matcher(&["--regex", r"\.jpg$", "out", "--include", r"!^tmp/"]);
+        assert!(regexes.matches(Path::new("photos/a.jpg")));
+        assert!(!regexes.matches(Path::new("tmp/a.jpg")));
+    }
+
+    #[test]
+    fn negated_patterns_alone_filter_a_list() {
+        let matcher = matcher(&["--files-from", "list", "out", "--include", "!*.tmp"]);
+        assert!(matcher.matches(Path::new("a.jpg")));
+        assert!(!matcher.matches(Path::new("a.tmp")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn listed_names_keep_bytes_that_are_not_utf8() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let list = std::env::temp_dir().join(format!("divisioner-list-{}", std::process::id()));
+        fs::write(&list, b"caf\xe9.txt\0dir/a b.txt\0\0").unwrap();
+        let paths = read_list(list.to_str().unwrap(), true);
+        fs::remove_file(&list).unwrap();
+        let paths = paths.unwrap();
+        assert_eq!(paths.len(), 2);
+        assert_eq!(paths[0].as_os_str().as_bytes(), b"caf\xe9.txt");
+        assert_eq!(paths[1], Path::new("dir/a b.txt"));
+    }
+
+    #[test]
+    fn unreadable_listed_paths_are_skipped() {
+        let list = std::env::temp_dir().join(format!("divisioner-skip-{}", std::process::id()));
+        fs::write(&list, "Cargo.toml\nCargo.toml/inside\nno such file\n").unwrap();
+        let args = crate::parse_args(["divisioner", "out", "--files-from", list.to_str().unwrap()]);
+        let searched = search_files(args);
+        fs::remove_file(&list).unwrap();
+        let (files, report) = searched.unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path, Path::new("Cargo.toml"));
+        assert_eq!(report.missing, [Path::new("no such file")]);
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(report.skipped[0].0, Path::new("Cargo.toml/inside"));
+    }
+
+    #[test]
+    fn braces_expand_in_order() {
+        assert_eq!(expand_braces("*.{jpg,png}"), ["*.jpg", "*.png"]);
+        assert_eq!(expand_braces("{a,b}/{c,d}"), ["a/c", "a/d", "b/c", "b/d"]);
+        assert_eq!(expand_braces("x{,y}"), ["x", "xy"]);
+    }
+
+    #[test]
+    fn nested_braces_expand() {
+        assert_eq!(expand_braces("a{b,c{d,e}}f"), ["abf", "acdf", "acef"]);
+        assert_eq!(find_braces("a{b,c{d,e}}f"), Some((1, 10, vec![3])));
+    }
+
+    #[test]
+    fn braces_without_a_comma_are_literal() {
+        assert_eq!(expand_braces("a{b}c"), ["a{b}c"]);
+        assert_eq!(expand_braces("{}"), ["{}"]);
+        assert_eq!(expand_braces("{a,b"), ["{a,b"]);
+        assert_eq!(find_braces("a{b}c"), None);
+        assert_eq!(expand_braces("{x}{a,b}"), ["{x}a", "{x}b"]);
+    }
+
+    #[test]
+    fn commas_inside_classes_do_not_split() {
+        assert_eq!(expand_braces("{[,],x}"), ["[,]", "x"]);
+        assert_eq!(expand_braces("[{,}]"), ["[{,}]"]);
+        assert_eq!(find_braces("[{a,b}]"), None);
+    }
+
+    #[test]
+    fn regex_braces_are_quantifiers_not_alternatives() {
+        let matcher = matcher(&["--regex", r"^a{2}\.(jpg|png)$", "out"]);
+        assert!(matcher.matches(Path::new("aa.png")));
+        assert!(!matcher.matches(Path::new("a{2}.png")));
+        assert!(!matcher.matches(Path::new("a.png")));
+    }
+}
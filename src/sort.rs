@@ -0,0 +1,180 @@
+use std::cmp::Ordering;
+use std::path::Path;
+
+use clap::ValueEnum;
+
+use crate::{content, Entry};
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortBy {
+    /// File name
+    Name,
+    /// File name, comparing runs of digits as numbers (img2 before img10)
+    Natural,
+    /// Modification time, oldest first
+    Mtime,
+    /// Creation time where the platform records it, otherwise modification time, oldest first
+    Ctime,
+    /// File size, smallest first
+    Size,
+    /// Extension, then file name
+    Ext,
+    /// Number of path components, then path
+    PathDepth,
+}
+
+/// Sorts stably by `sort_by`, falling back to the full path for ties.
+pub fn sort_files(files: &mut [Entry], sort_by: SortBy, reverse: bool) {
+    files.sort_by(|a, b| {
+        let ordering = match sort_by {
+            SortBy::Name => file_name(&a.path).cmp(&file_name(&b.path)),
+            SortBy::Natural => natural_cmp(&file_name(&a.path), &file_name(&b.path)),
+            SortBy::Mtime => a.modified.cmp(&b.modified),
+            SortBy::Ctime => a.created.cmp(&b.created),
+            SortBy::Size => a.size.cmp(&b.size),
+            SortBy::Ext => content::extension(&a.path, "")
+                .cmp(&content::extension(&b.path, ""))
+                .then_with(|| file_name(&a.path).cmp(&file_name(&b.path))),
+            SortBy::PathDepth => a
+                .path
+                .components()
+                .count()
+                .cmp(&b.path.components().count()),
+        }
+        .then_with(|| a.path.cmp(&b.path));
+        if reverse {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    });
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Compares strings piece by piece, where a piece is either a run of digits or a run of
+/// anything else. Digit runs compare by value, so `img2` sorts before `img10`.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        let (Some(x), Some(y)) = (a.chars().next(), b.chars().next()) else {
+            return a.len().cmp(&b.len());
+        };
+        let (piece_a, rest_a) = split_piece(a, x.is_ascii_digit());
+        let (piece_b, rest_b) = split_piece(b, y.is_ascii_digit());
+        let ordering = if x.is_ascii_digit() && y.is_ascii_digit() {
+            let (value_a, value_b) = (
+                piece_a.trim_start_matches('0'),
+                piece_b.trim_start_matches('0'),
+            );
+            value_a
+                .len()
+                .cmp(&value_b.len())
+                .then_with(|| value_a.cmp(value_b))
+                .then_with(|| piece_a.len().cmp(&piece_b.len()))
+        } else {
+            piece_a.cmp(piece_b)
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+        (a, b) = (rest_a, rest_b);
+    }
+}
+
+fn split_piece(s: &str, digits: bool) -> (&str, &str) {
+    let end = s
+        .find(|c: char| c.is_ascii_digit() != digits)
+        .unwrap_or(s.len());
+    s.split_at(end)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::time::UNIX_EPOCH;
+
+    use super::*;
+
+    fn entry(path: &str, size: u64) -> Entry {
+        Entry {
+            path: PathBuf::from(path),
+            name: path.to_string(),
+            size,
+            modified: UNIX_EPOCH,
+            created: UNIX_EPOCH,
+            key: None,
+            link: None,
+        }
+    }
+
+    fn sorted(paths: &[(&str, u64)], sort_by: SortBy, reverse: bool) -> Vec<String> {
+        let mut files = paths
+            .iter()
+            .map(|&(path, size)| entry(path, size))
+            .collect::<Vec<_>>();
+        sort_files(&mut files, sort_by, reverse);
+        files.into_iter().map(|file| file.name).collect()
+    }
+
+    #[test]
+    fn digit_runs_compare_by_value() {
+        assert_eq!(natural_cmp("img2", "img10"), Ordering::Less);
+        assert_eq!(natural_cmp("img10", "img9"), Ordering::Greater);
+        assert_eq!(natural_cmp("img2b", "img2a"), Ordering::Greater);
+        assert_eq!(natural_cmp("a10b2", "a10b10"), Ordering::Less);
+        assert_eq!(natural_cmp("img", "img1"), Ordering::Less);
+        assert_eq!(natural_cmp("img1", "img1"), Ordering::Equal);
+    }
+
+    #[test]
+    fn leading_zeros_only_break_ties() {
+        assert_eq!(natural_cmp("img002", "img10"), Ordering::Less);
+        assert_eq!(natural_cmp("img2", "img02"), Ordering::Less);
+        assert_eq!(natural_cmp("img02", "img002"), Ordering::Less);
+        assert_eq!(natural_cmp("img0", "img00"), Ordering::Less);
+    }
+
+    #[test]
+    fn natural_sort_orders_file_names() {
+        let files = [("img10.jpg", 0), ("img2.jpg", 0), ("img1.jpg", 0)];
+        assert_eq!(
+            sorted(&files, SortBy::Natural, false),
+            ["img1.jpg", "img2.jpg", "img10.jpg"]
+        );
+        assert_eq!(
+            sorted(&files, SortBy::Name, false),
+            ["img1.jpg", "img10.jpg", "img2.jpg"]
+        );
+    }
+
+    #[test]
+    fn ties_fall_back_to_the_path() {
+        let files = [("b/x.txt", 1), ("a/y.txt", 2), ("a/x.txt", 1)];
+        assert_eq!(
+            sorted(&files, SortBy::Size, false),
+            ["a/x.txt", "b/x.txt", "a/y.txt"]
+        );
+        assert_eq!(
+            sorted(&files, SortBy::Name, false),
+            ["a/x.txt", "b/x.txt", "a/y.txt"]
+        );
+    }
+
+    #[test]
+    fn reverse_mirrors_the_order_ties_included() {
+        let files = [("b/x.txt", 1), ("a/y.txt", 2), ("a/x.txt", 1)];
+        assert_eq!(
+            sorted(&files, SortBy::Size, true),
+            ["a/y.txt", "b/x.txt", "a/x.txt"]
+        );
+        assert_eq!(
+            sorted(&files, SortBy::Natural, true),
+            ["a/y.txt", "b/x.txt", "a/x.txt"]
+        );
+    }
+}
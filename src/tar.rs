@@ -0,0 +1,265 @@
+//! A writer for POSIX ustar archives. Paths that do not fit the ustar name and prefix fields,
+//! and long symlink targets, are written with GNU long name entries, and sizes beyond the
+//! octal field with the GNU base-256 encoding, which GNU tar and bsdtar both read.
+
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const BLOCK: usize = 512;
+
+pub struct TarWriter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> TarWriter<W> {
+    pub fn new(out: W) -> TarWriter<W> {
+        TarWriter { out }
+    }
+
+    pub fn add_file(
+        &mut self,
+        name: &str,
+        mode: u32,
+        modified: SystemTime,
+        data: &[u8],
+    ) -> io::Result<()> {
+        self.write_header(name, mode, modified, b'0', data.len() as u64, "")?;
+        self.out.write_all(data)?;
+        self.pad(data.len() as u64)
+    }
+
+    pub fn add_symlink(
+        &mut self,
+        name: &str,
+        modified: SystemTime,
+        target: &str,
+    ) -> io::Result<()> {
+        self.write_header(name, 0o777, modified, b'2', 0, target)
+    }
+
+    /// Writes the two empty blocks that end an archive and hands back the output.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.write_all(&[0; BLOCK * 2])?;
+        Ok(self.out)
+    }
+
+    fn write_header(
+        &mut self,
+        name: &str,
+        mode: u32,
+        modified: SystemTime,
+        kind: u8,
+        size: u64,
+        link: &str,
+    ) -> io::Result<()> {
+        if link.len() > 100 {
+            self.write_long_name(b'K', link)?;
+        }
+        let (prefix, short_name) = match split_name(name) {
+            Some(split) => split,
+            None => {
+                self.write_long_name(b'L', name)?;
+                ("", truncate(name, 100))
+            }
+        };
+        let mtime = modified
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        let mut header = [0u8; BLOCK];
+        header[..short_name.len()].copy_from_slice(short_name.as_bytes());
+        write_octal(&mut header[100..108], u64::from(mode));
+        write_octal(&mut header[108..116], 0);
+        write_octal(&mut header[116..124], 0);
+        write_number(&mut header[124..136], size);
+        write_octal(&mut header[136..148], mtime);
+        header[156] = kind;
+        let link = truncate(link, 100);
+        header[157..157 + link.len()].copy_from_slice(link.as_bytes());
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+        header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+        // The checksum is taken with its own field filled with spaces.
+        header[148..156].fill(b' ');
+        let checksum = header.iter().map(|&b| u32::from(b)).sum::<u32>();
+        write_octal(&mut header[148..155], u64::from(checksum));
+        self.out.write_all(&header)
+    }
+
+    /// A GNU `././@LongLink` entry whose data is the full name (`L`) or link target (`K`) of
+    /// the entry that follows it.
+    fn write_long_name(&mut self, kind: u8, name: &str) -> io::Result<()> {
+        let mut data = name.as_bytes().to_vec();
+        data.push(0);
+        self.write_header(
+            "././@LongLink",
+            0o644,
+            UNIX_EPOCH,
+            kind,
+            data.len() as u64,
+            "",
+        )?;
+        self.out.write_all(&data)?;
+        self.pad(data.len() as u64)
+    }
+
+    fn pad(&mut self, len: u64) -> io::Result<()> {
+        let rest = (len % BLOCK as u64) as usize;
+        if rest > 0 {
+            self.out.write_all(&[0; BLOCK][rest..])?;
+        }
+        Ok(())
+    }
+}
+
+/// Splits a name into the ustar prefix (up to 155 bytes) and name (up to 100 bytes) at a `/`.
+fn split_name(name: &str) -> Option<(&str, &str)> {
+    if name.len() <= 100 {
+        return Some(("", name));
+    }
+    name.match_indices('/')
+        .map(|(i, _)| (&name[..i], &name[i + 1..]))
+        .find(|(prefix, rest)| prefix.len() <= 155 && rest.len() <= 100 && !rest.is_empty())
+}
+
+fn truncate(s: &str, max: usize) -> &str {
+    let mut end = s.len().min(max);
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+/// Zero-padded octal followed by a NUL, filling the field.
+fn write_octal(field: &mut [u8], value: u64) {
+    let digits = format!("{:0width$o}", value, width = field.len() - 1);
+    field[..digits.len()].copy_from_slice(digits.as_bytes());
+    field[digits.len()] = 0;
+}
+
+/// Octal while the value fits the field, otherwise big-endian base-256 marked by the high bit.
+fn write_number(field: &mut [u8], value: u64) {
+    if value < 1 << (3 * (field.len() - 1)) {
+        return write_octal(field, value);
+    }
+    field.fill(0);
+    let bytes = value.to_be_bytes();
+    let len = field.len();
+    field[len - bytes.len()..].copy_from_slice(&bytes);
+    field[0] = 0x80;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn archive(build: impl FnOnce(&mut TarWriter<Vec<u8>>)) -> Vec<u8> {
+        let mut tar = TarWriter::new(Vec::new());
+        build(&mut tar);
+        tar.finish().unwrap()
+    }
+
+    fn field(header: &[u8], range: std::ops::Range<usize>) -> &str {
+        std::str::from_utf8(&header[range])
+            .unwrap()
+            .trim_end_matches('\0')
+    }
+
+    fn checksum_holds(header: &[u8]) -> bool {
+        let stored = u32::from_str_radix(field(header, 148..155), 8).unwrap();
+        let sum = header
+            .iter()
+            .enumerate()
+            .map(|(i, &b)| {
+                if (148..156).contains(&i) {
+                    32
+                } else {
+                    u32::from(b)
+                }
+            })
+            .sum::<u32>();
+        stored == sum
+    }
+
+    #[test]
+    fn file_header_layout() {
+        let modified = UNIX_EPOCH + Duration::from_secs(0o1234567);
+        let tar = archive(|tar| {
+            tar.add_file("dir/a.txt", 0o755, modified, b"hello")
+                .unwrap()
+        });
+        let header = &tar[..BLOCK];
+        assert_eq!(field(header, 0..100), "dir/a.txt");
+        assert_eq!(field(header, 100..108), "0000755");
+        assert_eq!(field(header, 124..136), "00000000005");
+        assert_eq!(field(header, 136..148), "00001234567");
+        assert_eq!(header[156], b'0');
+        assert_eq!(&header[257..265], b"ustar\x0000");
+        assert!(checksum_holds(header));
+        assert_eq!(&tar[BLOCK..BLOCK + 5], b"hello");
+        // Data padded to a block, then the two empty end blocks.
+        assert_eq!(tar.len(), BLOCK * 4);
+        assert!(tar[BLOCK + 5..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn symlink_header_layout() {
+        let tar = archive(|tar| tar.add_symlink("link", UNIX_EPOCH, "target").unwrap());
+        assert_eq!(tar[156], b'2');
+        assert_eq!(field(&tar, 157..257), "target");
+        assert_eq!(field(&tar, 124..136), "00000000000");
+        assert!(checksum_holds(&tar[..BLOCK]));
+        assert_eq!(tar.len(), BLOCK * 3);
+    }
+
+    #[test]
+    fn long_names_use_the_prefix_field() {
+        let name = format!("{}/{}", "d".repeat(120), "f".repeat(90));
+        assert_eq!(split_name(&name), Some((&name[..120], &name[121..])));
+        let tar = archive(|tar| tar.add_file(&name, 0o644, UNIX_EPOCH, b"").unwrap());
+        assert_eq!(field(&tar, 0..100), "f".repeat(90));
+        assert_eq!(field(&tar, 345..500), "d".repeat(120));
+        assert!(checksum_holds(&tar[..BLOCK]));
+    }
+
+    #[test]
+    fn names_that_do_not_split_get_a_long_link_entry() {
+        let name = "n".repeat(150);
+        assert_eq!(split_name(&name), None);
+        assert_eq!(split_name(&format!("{}/", "d".repeat(120))), None);
+        let tar = archive(|tar| tar.add_file(&name, 0o644, UNIX_EPOCH, b"x").unwrap());
+        assert_eq!(field(&tar, 0..100), "././@LongLink");
+        assert_eq!(tar[156], b'L');
+        assert_eq!(field(&tar, 124..136), "00000000227");
+        assert_eq!(&tar[BLOCK..BLOCK + 151], format!("{}\0", name).as_bytes());
+        let header = &tar[BLOCK * 2..BLOCK * 3];
+        assert_eq!(field(header, 0..100), "n".repeat(100));
+        assert_eq!(header[156], b'0');
+        assert!(checksum_holds(header));
+    }
+
+    #[test]
+    fn long_link_targets_get_a_long_link_entry() {
+        let target = "t".repeat(130);
+        let tar = archive(|tar| tar.add_symlink("link", UNIX_EPOCH, &target).unwrap());
+        assert_eq!(tar[156], b'K');
+        assert_eq!(&tar[BLOCK..BLOCK + 131], format!("{}\0", target).as_bytes());
+        assert_eq!(field(&tar[BLOCK * 2..], 0..100), "link");
+    }
+
+    #[test]
+    fn truncate_keeps_whole_characters() {
+        assert_eq!(truncate("aé", 2), "a");
+        assert_eq!(truncate("abc", 5), "abc");
+    }
+
+    #[test]
+    fn sizes_beyond_the_octal_field_use_base_256() {
+        let mut field = [0u8; 12];
+        write_number(&mut field, 0o77777777777);
+        assert_eq!(&field, b"77777777777\0");
+        write_number(&mut field, 1 << 33);
+        assert_eq!(field, [0x80, 0, 0, 0, 0, 0, 0, 0x02, 0, 0, 0, 0]);
+    }
+}